@@ -0,0 +1,453 @@
+use crate::api::logging::{self, Level};
+use futures_util::StreamExt;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Body, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::ReaderStream;
+
+/// Largest json body stored inline in an interaction, larger bodies stay in their file
+const INLINE_BODY_LIMIT: usize = 1024 * 1024;
+
+/// Response body as stored in a cassette
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum RecordedBody {
+    /// Json body stored inline
+    Json { body: Value },
+    /// Body stored in a file next to the interaction
+    File {
+        file: String,
+        sha256: String,
+        bytes: usize,
+    },
+}
+
+/// Request and response pair
+#[derive(Debug, Serialize, Deserialize)]
+struct Interaction {
+    method: String,
+    /// Url path, so cassettes replay against any tracking uri
+    path: String,
+    request: Value,
+    status: u16,
+    content_type: Option<String>,
+    response: RecordedBody,
+}
+
+/// Directory of recorded interactions
+pub struct Cassette {
+    dir: PathBuf,
+    /// Interactions and whether they were replayed, empty when recording
+    interactions: Mutex<Vec<(Interaction, bool)>>,
+    /// Number of interactions written
+    recorded: Mutex<usize>,
+}
+
+enum CassetteMode {
+    Record(Cassette),
+    Replay(Cassette),
+}
+
+static CASSETTE: OnceLock<CassetteMode> = OnceLock::new();
+
+/// Path of a request url
+///
+/// * `url` - request url
+fn url_path(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .map(|url| url.path().to_string())
+        .unwrap_or_else(|_| url.to_string())
+}
+
+/// Interaction files in a directory, in recording order
+///
+/// * `dir` - cassette directory
+fn interaction_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Streams a response body to a file
+///
+/// Returns the sha256 and size of the body.
+///
+/// * `path` - file to write
+/// * `response` - server response
+async fn write_body(path: &Path, response: Response) -> Result<(String, usize), String> {
+    let write_error = |e: std::io::Error| format!("Failed to write {}: {}", path.display(), e);
+    let mut file = tokio::fs::File::create(path).await.map_err(write_error)?;
+    let mut hasher = Sha256::new();
+    let mut bytes = 0;
+
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| e.to_string())?;
+        hasher.update(&chunk);
+        bytes += chunk.len();
+        file.write_all(&chunk).await.map_err(write_error)?;
+    }
+    file.flush().await.map_err(write_error)?;
+
+    Ok((format!("{:x}", hasher.finalize()), bytes))
+}
+
+/// Response body streamed from a file
+///
+/// * `path` - body file
+fn file_body(path: &Path) -> Result<Body, String> {
+    let file =
+        fs::File::open(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Ok(Body::wrap_stream(ReaderStream::new(
+        tokio::fs::File::from_std(file),
+    )))
+}
+
+impl Cassette {
+    /// Creates an empty cassette directory to record into
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Cassette directory
+    pub fn create(dir: &str) -> Result<Self, String> {
+        let dir = PathBuf::from(dir);
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+        if !interaction_files(&dir)?.is_empty() {
+            return Err(format!(
+                "{} already contains recordings, record into an empty directory",
+                dir.display()
+            ));
+        }
+
+        Ok(Cassette {
+            dir,
+            interactions: Mutex::new(Vec::new()),
+            recorded: Mutex::new(0),
+        })
+    }
+
+    /// Loads a recorded cassette
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Cassette directory
+    pub fn load(dir: &str) -> Result<Self, String> {
+        let dir = PathBuf::from(dir);
+        let mut interactions = Vec::new();
+
+        for path in interaction_files(&dir)? {
+            let data = fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            let interaction: Interaction = serde_json::from_str(&data)
+                .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+            interactions.push((interaction, false));
+        }
+
+        if interactions.is_empty() {
+            return Err(format!("No recordings found in {}", dir.display()));
+        }
+
+        Ok(Cassette {
+            dir,
+            interactions: Mutex::new(interactions),
+            recorded: Mutex::new(0),
+        })
+    }
+
+    /// Writes a response to the cassette and returns an equivalent response
+    ///
+    /// Bodies are streamed to a file; small json bodies are then moved into the interaction.
+    ///
+    /// * `url` - request url
+    /// * `request` - request body
+    /// * `response` - server response
+    async fn record(
+        &self,
+        url: &str,
+        request: &[u8],
+        response: Response,
+    ) -> Result<Response, String> {
+        let status = response.status();
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+
+        let index = {
+            let mut recorded = self.recorded.lock().unwrap();
+            *recorded += 1;
+            *recorded
+        };
+
+        let file = format!("{:04}.body", index);
+        let body_path = self.dir.join(&file);
+        let (sha256, bytes) = write_body(&body_path, response).await?;
+
+        let is_json = content_type
+            .as_deref()
+            .is_some_and(|content_type| content_type.starts_with("application/json"));
+        let inline = if is_json && bytes <= INLINE_BODY_LIMIT {
+            fs::read(&body_path)
+                .ok()
+                .and_then(|data| Some((serde_json::from_slice::<Value>(&data).ok()?, data)))
+        } else {
+            None
+        };
+
+        let (recorded_body, body) = match inline {
+            Some((json, data)) => {
+                fs::remove_file(&body_path)
+                    .map_err(|e| format!("Failed to remove {}: {}", file, e))?;
+                (RecordedBody::Json { body: json }, Body::from(data))
+            }
+            None => (
+                RecordedBody::File {
+                    file,
+                    sha256,
+                    bytes,
+                },
+                file_body(&body_path)?,
+            ),
+        };
+
+        let interaction = Interaction {
+            method: "POST".to_string(),
+            path: url_path(url),
+            request: serde_json::from_slice(request).unwrap_or(Value::Null),
+            status: status.as_u16(),
+            content_type: content_type.clone(),
+            response: recorded_body,
+        };
+
+        let path = self.dir.join(format!("{:04}.json", index));
+        let data = serde_json::to_string_pretty(&interaction).map_err(|e| e.to_string())?;
+        fs::write(&path, data).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+        build_response(status.as_u16(), content_type.as_deref(), body)
+    }
+
+    /// Finds the recorded response to a request
+    ///
+    /// Recordings are replayed in order; once all matching recordings were used the last one
+    /// is repeated, so polling commands keep working.
+    ///
+    /// * `url` - request url
+    /// * `request` - request body
+    fn replay(&self, url: &str, request: &[u8]) -> Result<Response, String> {
+        let path = url_path(url);
+        let request: Value = serde_json::from_slice(request).unwrap_or(Value::Null);
+        let mut interactions = self.interactions.lock().unwrap();
+
+        let matching: Vec<usize> = interactions
+            .iter()
+            .enumerate()
+            .filter(|(_, (i, _))| i.method == "POST" && i.path == path && i.request == request)
+            .map(|(index, _)| index)
+            .collect();
+
+        let index = matching
+            .iter()
+            .find(|index| !interactions[**index].1)
+            .or(matching.last())
+            .copied()
+            .ok_or_else(|| {
+                format!(
+                    "No recording of POST {} {} in {}",
+                    path,
+                    request,
+                    self.dir.display()
+                )
+            })?;
+
+        interactions[index].1 = true;
+        let interaction = &interactions[index].0;
+
+        let body = match &interaction.response {
+            RecordedBody::Json { body } => {
+                Body::from(serde_json::to_vec(body).map_err(|e| e.to_string())?)
+            }
+            RecordedBody::File { file, .. } => file_body(&self.dir.join(file))?,
+        };
+
+        // recordings may have been edited by hand
+        build_response(
+            interaction.status,
+            interaction.content_type.as_deref(),
+            body,
+        )
+        .map_err(|e| {
+            format!(
+                "Invalid recording of POST {} in {}: {}",
+                path,
+                self.dir.display(),
+                e
+            )
+        })
+    }
+}
+
+/// Builds a response from recorded parts
+///
+/// * `status` - status code
+/// * `content_type` - content type header
+/// * `body` - response body
+fn build_response(status: u16, content_type: Option<&str>, body: Body) -> Result<Response, String> {
+    let mut builder = http::Response::builder().status(status);
+    if let Some(content_type) = content_type {
+        builder = builder.header(CONTENT_TYPE, content_type);
+    }
+
+    builder
+        .body(body)
+        .map(Response::from)
+        .map_err(|e| e.to_string())
+}
+
+/// Sets up recording or replaying of requests for the rest of the process
+///
+/// # Arguments
+///
+/// * `record` - Directory to record requests and responses into
+/// * `replay` - Directory to replay responses from instead of the network
+pub fn init(record: Option<&str>, replay: Option<&str>) -> Result<(), String> {
+    let mode = match (record, replay) {
+        (Some(dir), _) => CassetteMode::Record(Cassette::create(dir)?),
+        (None, Some(dir)) => CassetteMode::Replay(Cassette::load(dir)?),
+        (None, None) => return Ok(()),
+    };
+
+    let _ = CASSETTE.set(mode);
+    Ok(())
+}
+
+/// Whether responses are replayed instead of requested from the server
+pub fn replaying() -> bool {
+    matches!(CASSETTE.get(), Some(CassetteMode::Replay(_)))
+}
+
+/// Returns the recorded response when replaying
+///
+/// Fails when the cassette has no recording of the request.
+///
+/// # Arguments
+///
+/// * `url` - Request url
+/// * `request` - Request body
+pub fn replay(url: &str, request: &[u8]) -> Result<Option<Response>, String> {
+    let cassette = match CASSETTE.get() {
+        Some(CassetteMode::Replay(cassette)) => cassette,
+        _ => return Ok(None),
+    };
+
+    let response = cassette.replay(url, request)?;
+    logging::log(
+        Level::Info,
+        "replayed http response",
+        &[
+            ("method", "POST".into()),
+            ("url", url.into()),
+            ("status", response.status().as_u16().into()),
+        ],
+    );
+    Ok(Some(response))
+}
+
+/// Records a response when recording, otherwise returns it unchanged
+///
+/// # Arguments
+///
+/// * `url` - Request url
+/// * `request` - Request body
+/// * `response` - Server response
+pub async fn record(url: &str, request: &[u8], response: Response) -> Result<Response, String> {
+    match CASSETTE.get() {
+        Some(CassetteMode::Record(cassette)) => cassette
+            .record(url, request, response)
+            .await
+            .map_err(|e| format!("Failed to record response: {}", e)),
+        _ => Ok(response),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cassette() {
+        let dir = std::env::temp_dir().join("opsml_cassette_test");
+        let _ = fs::remove_dir_all(&dir);
+        let dir_str = dir.to_string_lossy().to_string();
+
+        let url = "http://localhost:8080/opsml/models/metadata";
+        let request = br#"{"name":"model","version":null,"uid":null}"#;
+        let cassette = Cassette::create(&dir_str).unwrap();
+
+        let json = build_response(200, Some("application/json"), Body::from(r#"{"a":1}"#)).unwrap();
+        let response = cassette.record(url, request, json).await.unwrap();
+        assert_eq!(response.text().await.unwrap(), r#"{"a":1}"#);
+
+        let download_url = "http://localhost:8080/opsml/files/download";
+        let download = build_response(200, None, Body::from("onnx")).unwrap();
+        cassette
+            .record(download_url, b"{}", download)
+            .await
+            .unwrap();
+
+        // json too large to inline is kept in its body file
+        let large = format!("[{}0]", "0,".repeat(INLINE_BODY_LIMIT));
+        let list_url = "http://localhost:8080/opsml/cards/list";
+        let response =
+            build_response(200, Some("application/json"), Body::from(large.clone())).unwrap();
+        let response = cassette.record(list_url, b"{}", response).await.unwrap();
+        assert_eq!(response.text().await.unwrap(), large);
+        assert!(dir.join("0003.body").exists());
+        assert!(!dir.join("0001.body").exists());
+        assert!(Cassette::create(&dir_str).is_err());
+
+        // replays are matched on path and body, not host
+        let cassette = Cassette::load(&dir_str).unwrap();
+        let replayed = cassette
+            .replay("http://opsml.internal/opsml/models/metadata", request)
+            .unwrap();
+        assert_eq!(replayed.status(), 200);
+        assert_eq!(replayed.text().await.unwrap(), r#"{"a":1}"#);
+
+        let replayed = cassette.replay(download_url, b"{}").unwrap();
+        assert_eq!(replayed.bytes().await.unwrap().as_ref(), b"onnx");
+
+        let replayed = cassette.replay(list_url, b"{}").unwrap();
+        assert_eq!(replayed.text().await.unwrap(), large);
+
+        assert!(cassette.replay(url, b"{}").is_err());
+
+        // a hand-edited status code is an error instead of a panic
+        let path = dir.join("0001.json");
+        let mut interaction: Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        interaction["status"] = 1000.into();
+        fs::write(&path, interaction.to_string()).unwrap();
+        let cassette = Cassette::load(&dir_str).unwrap();
+        let error = cassette
+            .replay("http://opsml.internal/opsml/models/metadata", request)
+            .unwrap_err();
+        assert!(error.starts_with("Invalid recording of POST /opsml/models/metadata"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,13 +1,23 @@
+use crate::api::compare_report::ReportFormat;
+use crate::api::compare_spec::MissingPolicy;
+use crate::api::completions::{
+    complete_card_name, complete_registry, complete_version, CompletionShell,
+};
+use crate::api::lockfile::ArtifactKind;
+use crate::api::output::OutputFormat;
+use crate::api::resolve::ModelSelector;
 use clap::Args;
+use clap_complete::ArgValueCompleter;
+use std::time::Duration;
 
 #[derive(Args)]
 pub struct ListCards {
     /// Name of the registry (data, model, run, etc)
-    #[arg(long = "registry")]
+    #[arg(long = "registry", add = ArgValueCompleter::new(complete_registry))]
     pub registry: String,
 
     /// Name given to a card
-    #[arg(long = "name")]
+    #[arg(long = "name", add = ArgValueCompleter::new(complete_card_name))]
     pub name: Option<String>,
 
     /// Team name
@@ -15,7 +25,7 @@ pub struct ListCards {
     pub team: Option<String>,
 
     /// Card version
-    #[arg(long = "version")]
+    #[arg(long = "version", add = ArgValueCompleter::new(complete_version))]
     pub version: Option<String>,
 
     /// Card uid
@@ -42,67 +52,111 @@ pub struct ListCards {
 #[derive(Args)]
 pub struct ModelMetadataArgs {
     /// Name given to card
-    #[arg(long = "name")]
+    #[arg(long = "name", env = "OPSML_MODEL_NAME", add = ArgValueCompleter::new(complete_card_name))]
     pub name: Option<String>,
 
     /// Card version
-    #[arg(long = "version")]
+    #[arg(long = "version", env = "OPSML_MODEL_VERSION", add = ArgValueCompleter::new(complete_version))]
     pub version: Option<String>,
 
     /// Card uid
-    #[arg(long = "uid")]
+    #[arg(long = "uid", env = "OPSML_MODEL_UID")]
     pub uid: Option<String>,
 
     /// Write directory
-    #[arg(long = "write-dir", default_value = ".models")]
+    #[arg(long = "write-dir", env = "OPSML_WRITE_DIR", default_value = ".models")]
     pub write_dir: String,
+
+    /// File written once the download succeeds
+    #[arg(long = "ready-file", env = "OPSML_READY_FILE")]
+    pub ready_file: Option<String>,
+
+    /// Only print the json log line describing the download, without progress text
+    #[arg(long = "json-log", env = "OPSML_JSON_LOG", default_value = "false")]
+    pub json_log: bool,
 }
 
 #[derive(Args)]
 pub struct DownloadModelArgs {
     /// Name given to card
-    #[arg(long = "name")]
+    #[arg(long = "name", env = "OPSML_MODEL_NAME", add = ArgValueCompleter::new(complete_card_name))]
     pub name: Option<String>,
 
     /// Card version
-    #[arg(long = "version")]
+    #[arg(long = "version", env = "OPSML_MODEL_VERSION", add = ArgValueCompleter::new(complete_version))]
     pub version: Option<String>,
 
     /// Card uid
-    #[arg(long = "uid")]
+    #[arg(long = "uid", env = "OPSML_MODEL_UID")]
     pub uid: Option<String>,
 
     /// Write directory
-    #[arg(long = "write-dir", default_value = ".models")]
+    #[arg(long = "write-dir", env = "OPSML_WRITE_DIR", default_value = ".models")]
     pub write_dir: String,
 
     /// Boolean indicating whether to download onnx or trained model
-    #[arg(long = "no-onnx", default_value = "false")]
+    #[arg(long = "no-onnx", env = "OPSML_NO_ONNX", default_value = "false")]
     pub no_onnx: bool,
 
     /// Boolean indicating whether to download onnx or trained model
-    #[arg(long = "onnx", default_value = "true")]
+    #[arg(long = "onnx", env = "OPSML_ONNX", default_value = "true")]
     pub onnx: bool,
+
+    /// File written once the download succeeds
+    #[arg(long = "ready-file", env = "OPSML_READY_FILE")]
+    pub ready_file: Option<String>,
+
+    /// Only print the json log line describing the download, without progress text
+    #[arg(long = "json-log", env = "OPSML_JSON_LOG", default_value = "false")]
+    pub json_log: bool,
 }
 
 #[derive(Args)]
 pub struct ModelMetricArgs {
     /// Name given to card
-    #[arg(long = "name")]
+    #[arg(long = "name", add = ArgValueCompleter::new(complete_card_name))]
     pub name: Option<String>,
 
     /// Card version
-    #[arg(long = "version")]
+    #[arg(long = "version", add = ArgValueCompleter::new(complete_version))]
     pub version: Option<String>,
 
     /// Card uid
     #[arg(long = "uid")]
     pub uid: Option<String>,
+
+    /// Metric names or globs to show (e.g. loss or val_*), repeatable
+    #[arg(long = "metric")]
+    pub metric: Vec<String>,
+
+    /// Regexes matching metric names to show, repeatable
+    #[arg(long = "metric-regex")]
+    pub metric_regex: Vec<String>,
+
+    /// Smallest step to show
+    #[arg(long = "min-step")]
+    pub min_step: Option<f64>,
+
+    /// Largest step to show
+    #[arg(long = "max-step")]
+    pub max_step: Option<f64>,
+
+    /// Only show the last step of each metric
+    #[arg(long = "latest-only", default_value = "false")]
+    pub latest_only: bool,
+
+    /// Show every logged step instead of a summary per metric
+    #[arg(long = "full", default_value = "false")]
+    pub full: bool,
+
+    /// Output format, json and csv list every value
+    #[arg(long = "output", value_enum, default_value_t = OutputFormat::Table)]
+    pub output: OutputFormat,
 }
 
 #[derive(Args)]
 pub struct CompareMetricArgs {
-    /// Metric name
+    /// Metric names, optionally with a direction (e.g. mae:min,auc:max)
     #[arg(
         long = "metric-name",
         use_value_delimiter = true,
@@ -110,25 +164,283 @@ pub struct CompareMetricArgs {
     )]
     pub metric_name: Vec<String>,
 
-    /// If lower is better
+    /// If lower is better, one value per metric name
     #[arg(
         long = "lower-is-better",
         use_value_delimiter = true,
-        value_delimiter = ',',
-        default_value = "true"
+        value_delimiter = ','
     )]
     pub lower_is_better: Vec<bool>,
 
     /// Id of new model challenger
-    #[arg(long = "challenger-uid")]
-    pub challenger_uid: String,
+    #[arg(long = "challenger-uid", conflicts_with = "challenger")]
+    pub challenger_uid: Option<String>,
 
-    /// Id of new model challenger
+    /// New model challenger as name@version or name@latest
+    #[arg(long = "challenger")]
+    pub challenger: Option<ModelSelector>,
+
+    /// Ids of champion models
     #[arg(
         long = "champion-uid",
         use_value_delimiter = true,
-        value_delimiter = ',',
-        default_value = "true"
+        value_delimiter = ','
     )]
     pub champion_uid: Vec<String>,
+
+    /// Champion models as name@version, name@latest, name@last:N or tag:key=value
+    #[arg(long = "champion", use_value_delimiter = true, value_delimiter = ',')]
+    pub champion: Vec<ModelSelector>,
+
+    /// Yaml or toml file listing metrics, directions, tolerances and champions
+    #[arg(long = "spec")]
+    pub spec: Option<String>,
+
+    /// Exit with a failure code if the challenger loses any battle
+    #[arg(long = "fail-on-loss", default_value = "false")]
+    pub fail_on_loss: bool,
+
+    /// Minimum number of battles the challenger must win
+    #[arg(long = "require-wins")]
+    pub require_wins: Option<usize>,
+
+    /// Percent the challenger may be worse by, per metric (e.g. latency=1%)
+    #[arg(
+        long = "tolerance",
+        use_value_delimiter = true,
+        value_delimiter = ',',
+        value_parser = parse_tolerance
+    )]
+    pub tolerance: Vec<(String, f64)>,
+
+    /// How battles against champions missing a metric count: skip (neither win nor
+    /// loss), warn (challenger loss) or fail (error)
+    #[arg(long = "missing", value_enum)]
+    pub missing: Option<MissingPolicy>,
+
+    /// Summarise metrics logged over multiple steps (last, best and mean)
+    #[arg(long = "step-stats", default_value = "false")]
+    pub step_stats: bool,
+
+    /// Bootstrap resamples for a 95% confidence interval on the step mean delta
+    /// (implies --step-stats)
+    #[arg(long = "bootstrap")]
+    pub bootstrap: Option<usize>,
+
+    /// Write a markdown or html comparison report
+    #[arg(long = "report", value_enum, requires = "out")]
+    pub report: Option<ReportFormat>,
+
+    /// Report file path
+    #[arg(long = "out", requires = "report")]
+    pub out: Option<String>,
+
+    /// Write battles as a JUnit xml report
+    #[arg(long = "junit")]
+    pub junit: Option<String>,
+}
+
+/// Parses a duration of the form `30s`, `5m` or `1h`, in seconds without a unit
+///
+/// * `value` - duration argument (e.g. 60s)
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let (amount, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => value.split_at(i),
+        None => (value, "s"),
+    };
+
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("Invalid duration {}, expected e.g. 30s, 5m or 1h", value))?;
+
+    let seconds = match unit {
+        "s" => amount,
+        "m" => amount * 60,
+        "h" => amount * 3600,
+        _ => {
+            return Err(format!(
+                "Invalid duration unit {}, expected s, m or h",
+                unit
+            ))
+        }
+    };
+
+    if seconds == 0 {
+        return Err("Duration must be greater than zero".to_string());
+    }
+
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Parses a metric tolerance of the form `metric=percent`
+///
+/// * `value` - tolerance argument (e.g. latency=1% or latency=1)
+fn parse_tolerance(value: &str) -> Result<(String, f64), String> {
+    let (metric, tolerance) = value.split_once('=').ok_or(format!(
+        "Invalid tolerance {}, expected metric=percent",
+        value
+    ))?;
+
+    let tolerance: f64 = tolerance
+        .trim_end_matches('%')
+        .parse()
+        .map_err(|_| format!("Invalid tolerance percent {}", tolerance))?;
+
+    if tolerance < 0.0 {
+        return Err(format!("Tolerance for {} must not be negative", metric));
+    }
+
+    Ok((metric.to_string(), tolerance))
+}
+
+#[derive(Args)]
+pub struct PredictArgs {
+    /// Directory the model was downloaded to
+    #[arg(long = "write-dir", default_value = ".models")]
+    pub write_dir: String,
+
+    /// Json file mapping model input names to values (defaults to sample data)
+    #[arg(long = "payload")]
+    pub payload: Option<String>,
+}
+
+#[derive(Args)]
+pub struct InspectOnnxArgs {
+    /// Path to the onnx model
+    pub path: String,
+}
+
+#[derive(Args)]
+pub struct LeaderboardArgs {
+    /// Model name
+    #[arg(long = "name", required_unless_present = "team", add = ArgValueCompleter::new(complete_card_name))]
+    pub name: Option<String>,
+
+    /// Team name
+    #[arg(long = "team", conflicts_with = "name")]
+    pub team: Option<String>,
+
+    /// Metric to rank by with the direction in which it improves (e.g. auc:max or mae:min)
+    #[arg(long = "metric")]
+    pub metric: String,
+
+    /// Number of versions to show
+    #[arg(long = "top")]
+    pub top: Option<usize>,
+
+    /// Output format
+    #[arg(long = "output", value_enum, default_value_t = OutputFormat::Table)]
+    pub output: OutputFormat,
+}
+
+#[derive(Args)]
+pub struct PlotMetricsArgs {
+    /// Model uids to plot
+    #[arg(long = "uid", required = true)]
+    pub uid: Vec<String>,
+
+    /// Metric to plot
+    #[arg(long = "metric")]
+    pub metric: String,
+
+    /// Chart file, ending in .svg or .png
+    #[arg(long = "out")]
+    pub out: String,
+}
+
+#[derive(Args)]
+pub struct DiffMetricsArgs {
+    /// First model as uid or name@version
+    #[arg(long = "a")]
+    pub a: ModelSelector,
+
+    /// Second model as uid or name@version
+    #[arg(long = "b")]
+    pub b: ModelSelector,
+
+    /// Output format
+    #[arg(long = "output", value_enum, default_value_t = OutputFormat::Table)]
+    pub output: OutputFormat,
+}
+
+#[derive(Args)]
+pub struct LockArgs {
+    /// Manifest listing the models to pin
+    #[arg(long = "manifest", default_value = "opsml.toml")]
+    pub manifest: String,
+
+    /// Lockfile to write
+    #[arg(long = "lockfile", default_value = "opsml.lock")]
+    pub lockfile: String,
+}
+
+#[derive(Args)]
+pub struct SyncArgs {
+    /// Lockfile listing the models to download
+    #[arg(long = "lockfile", default_value = "opsml.lock")]
+    pub lockfile: String,
+}
+
+#[derive(Args)]
+pub struct WatchModelArgs {
+    /// Model name
+    #[arg(long = "name", add = ArgValueCompleter::new(complete_card_name))]
+    pub name: String,
+
+    /// Semver requirement versions must match (e.g. ^1.2)
+    #[arg(long = "version", default_value = "*", add = ArgValueCompleter::new(complete_version))]
+    pub version: String,
+
+    /// Time between registry checks (e.g. 30s, 5m, 1h)
+    #[arg(long = "interval", default_value = "60s", value_parser = parse_duration)]
+    pub interval: Duration,
+
+    /// Write directory
+    #[arg(long = "write-dir", default_value = ".models")]
+    pub write_dir: String,
+
+    /// Artifacts to download for each version
+    #[arg(long = "artifact", value_enum, default_values_t = [ArtifactKind::Onnx])]
+    pub artifact: Vec<ArtifactKind>,
+
+    /// Shell command run after each update, with OPSML_MODEL_VERSION and OPSML_MODEL_DIR set
+    #[arg(long = "hook")]
+    pub hook: Option<String>,
+
+    /// Number of previous versions kept for rollback
+    #[arg(long = "keep", default_value = "2")]
+    pub keep: usize,
+
+    /// Check the registry once and exit
+    #[arg(long = "once", default_value = "false")]
+    pub once: bool,
+}
+
+#[derive(Args)]
+pub struct MockServerArgs {
+    /// Directory of card, metadata, metric and artifact fixtures
+    #[arg(long = "fixtures")]
+    pub fixtures: String,
+
+    /// Host to listen on
+    #[arg(long = "host", default_value = "127.0.0.1")]
+    pub host: String,
+
+    /// Port to listen on, 0 picks a free port
+    #[arg(long = "port", default_value = "8090")]
+    pub port: u16,
+}
+
+#[derive(Args)]
+pub struct CompletionsArgs {
+    /// Shell to generate completions for
+    #[arg(value_enum)]
+    pub shell: CompletionShell,
+}
+
+#[derive(Args)]
+pub struct ManArgs {
+    /// Directory to write a man page per command to, prints the main page when omitted
+    #[arg(long = "out-dir")]
+    pub out_dir: Option<String>,
 }
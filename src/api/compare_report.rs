@@ -0,0 +1,424 @@
+use crate::api::metrics::{battle_delta, find_missing_metrics, Battle, CompareResult, Outcome};
+use crate::api::types;
+use clap::ValueEnum;
+
+/// Document format for comparison reports
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+/// Styles for the self-contained html report
+const HTML_STYLE: &str = "body { font-family: sans-serif; margin: 2em; color: #1f2328; }
+table { border-collapse: collapse; margin-bottom: 1.5em; }
+th, td { border: 1px solid #d0d7de; padding: 4px 10px; text-align: right; }
+th:first-child, td:first-child { text-align: left; }
+.badge { border-radius: 4px; padding: 1px 8px; color: #fff; font-weight: bold; }
+.win { background: #1a7f37; }
+.loss { background: #cf222e; }
+.tolerated { background: #9a6700; }
+.skipped { background: #6e7781; }
+.missing { color: #9a6700; }";
+
+/// Battles against a single champion
+struct ChampionSection<'a> {
+    name: &'a str,
+    version: &'a str,
+    battles: Vec<&'a Battle<'a>>,
+}
+
+/// Groups battles by champion, ordered by champion name and version
+///
+/// * `battles` - judged battles
+fn group_by_champion<'a>(battles: &'a [Battle<'a>]) -> Vec<ChampionSection<'a>> {
+    let mut sections: Vec<ChampionSection> = Vec::new();
+
+    for battle in battles.iter() {
+        let (name, version) = (
+            battle.report.champion_name.as_str(),
+            battle.report.champion_version.as_str(),
+        );
+        match sections
+            .iter_mut()
+            .find(|s| s.name == name && s.version == version)
+        {
+            Some(section) => section.battles.push(battle),
+            None => sections.push(ChampionSection {
+                name,
+                version,
+                battles: vec![battle],
+            }),
+        }
+    }
+
+    sections.sort_by(|a, b| (a.name, a.version).cmp(&(b.name, b.version)));
+    sections
+}
+
+/// Plain text cells of a battle row
+///
+/// * `battle` - judged battle
+fn battle_cells(battle: &Battle) -> Vec<String> {
+    let value = |metric: &Option<types::Metric>| match metric {
+        Some(metric) => metric.value.to_string(),
+        None => "missing".to_string(),
+    };
+    let direction = if battle.lower_is_better { "min" } else { "max" };
+    let deltas = match battle_delta(battle) {
+        Some(delta) => delta.cells().to_vec(),
+        None => vec!["-".to_string(); 3],
+    };
+
+    let mut cells = vec![
+        battle.metric.clone(),
+        direction.to_string(),
+        value(&battle.report.champion_metric),
+        value(&battle.report.challenger_metric),
+    ];
+    cells.extend(deltas);
+    cells
+}
+
+/// Table header shared by both report formats
+const HEADER: [&str; 8] = [
+    "Metric",
+    "Direction",
+    "Champion",
+    "Challenger",
+    "Delta",
+    "Change",
+    "Better by",
+    "Result",
+];
+
+fn verdict_text(result: &CompareResult) -> String {
+    format!(
+        "Challenger {} {}: {} wins, {} losses, {} within tolerance, {} skipped",
+        result.challenger_name,
+        result.challenger_version,
+        result.summary.wins,
+        result.summary.losses,
+        result.summary.tolerated,
+        result.summary.skipped
+    )
+}
+
+/// Renders a comparison as markdown
+///
+/// * `result` - judged comparison
+fn render_markdown(result: &CompareResult) -> String {
+    let escape = |cell: &str| cell.replace('|', "\\|");
+    let mut doc = format!(
+        "# Metric comparison: {} {}\n",
+        result.challenger_name, result.challenger_version
+    );
+
+    for section in group_by_champion(result.battles) {
+        doc.push_str(&format!(
+            "\n## Champion {} {}\n\n",
+            section.name, section.version
+        ));
+        doc.push_str(&format!("| {} |\n", HEADER.join(" | ")));
+        doc.push_str("| --- | --- | ---: | ---: | ---: | ---: | ---: | --- |\n");
+
+        for battle in section.battles {
+            let mut cells: Vec<String> = battle_cells(battle).iter().map(|c| escape(c)).collect();
+            cells.push(
+                match battle.outcome {
+                    Outcome::Win => "✅ win",
+                    Outcome::Loss => "❌ loss",
+                    Outcome::Tolerated => "⚠️ tolerated",
+                    Outcome::Skipped => "⏭️ skipped",
+                }
+                .to_string(),
+            );
+            doc.push_str(&format!("| {} |\n", cells.join(" | ")));
+        }
+    }
+
+    let missing = find_missing_metrics(result.battles);
+    if !missing.is_empty() {
+        doc.push_str("\n## Missing metrics\n\n");
+        for line in missing {
+            doc.push_str(&format!("- {}\n", line));
+        }
+    }
+
+    let verdict = if result.qualified {
+        "✅ **qualified**"
+    } else {
+        "❌ **not qualified**"
+    };
+    doc.push_str(&format!(
+        "\n## Verdict\n\n{} - {}\n",
+        verdict_text(result),
+        verdict
+    ));
+
+    doc
+}
+
+/// Escapes text for use in html and xml
+///
+/// * `text` - text to escape
+fn escape_markup(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Renders a comparison as a self-contained html document
+///
+/// * `result` - judged comparison
+fn render_html(result: &CompareResult) -> String {
+    let title = escape_markup(&format!(
+        "Metric comparison: {} {}",
+        result.challenger_name, result.challenger_version
+    ));
+    let mut doc = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}\n</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+        title, HTML_STYLE, title
+    );
+
+    for section in group_by_champion(result.battles) {
+        doc.push_str(&format!(
+            "<h2>Champion {} {}</h2>\n<table>\n<tr>",
+            escape_markup(section.name),
+            escape_markup(section.version)
+        ));
+        for header in HEADER.iter() {
+            doc.push_str(&format!("<th>{}</th>", header));
+        }
+        doc.push_str("</tr>\n");
+
+        for battle in section.battles {
+            doc.push_str("<tr>");
+            for cell in battle_cells(battle) {
+                let class = if cell == "missing" {
+                    " class=\"missing\""
+                } else {
+                    ""
+                };
+                doc.push_str(&format!("<td{}>{}</td>", class, escape_markup(&cell)));
+            }
+            let (class, label) = match battle.outcome {
+                Outcome::Win => ("win", "win"),
+                Outcome::Loss => ("loss", "loss"),
+                Outcome::Tolerated => ("tolerated", "tolerated"),
+                Outcome::Skipped => ("skipped", "skipped"),
+            };
+            doc.push_str(&format!(
+                "<td><span class=\"badge {}\">{}</span></td></tr>\n",
+                class, label
+            ));
+        }
+        doc.push_str("</table>\n");
+    }
+
+    let missing = find_missing_metrics(result.battles);
+    if !missing.is_empty() {
+        doc.push_str("<h2>Missing metrics</h2>\n<ul>\n");
+        for line in missing {
+            doc.push_str(&format!("<li>{}</li>\n", escape_markup(&line)));
+        }
+        doc.push_str("</ul>\n");
+    }
+
+    let (class, label) = if result.qualified {
+        ("win", "qualified")
+    } else {
+        ("loss", "not qualified")
+    };
+    doc.push_str(&format!(
+        "<h2>Verdict</h2>\n<p>{} - <span class=\"badge {}\">{}</span></p>\n</body>\n</html>\n",
+        escape_markup(&verdict_text(result)),
+        class,
+        label
+    ));
+
+    doc
+}
+
+/// Renders a judged comparison as a markdown or html document
+///
+/// # Arguments
+///
+/// * `format` - Report format
+/// * `result` - Judged comparison
+pub fn render_report(format: ReportFormat, result: &CompareResult) -> String {
+    match format {
+        ReportFormat::Markdown => render_markdown(result),
+        ReportFormat::Html => render_html(result),
+    }
+}
+
+/// Renders a judged comparison as a JUnit xml report
+///
+/// Each battle is a test case, grouped into one test suite per champion.
+/// Losses are failures, tolerated losses pass and skipped battles are reported as skipped.
+///
+/// # Arguments
+///
+/// * `result` - Judged comparison
+pub fn render_junit(result: &CompareResult) -> String {
+    let failures = |battles: &[&Battle]| {
+        battles
+            .iter()
+            .filter(|battle| battle.outcome == Outcome::Loss)
+            .count()
+    };
+    let value = |metric: &Option<types::Metric>| match metric {
+        Some(metric) => metric.value.to_string(),
+        None => "missing".to_string(),
+    };
+
+    let sections = group_by_champion(result.battles);
+    let all: Vec<&Battle> = sections.iter().flat_map(|s| s.battles.clone()).collect();
+    let mut doc = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites name=\"{}\" tests=\"{}\" failures=\"{}\">\n",
+        escape_markup(&format!(
+            "{} {}",
+            result.challenger_name, result.challenger_version
+        )),
+        all.len(),
+        failures(&all)
+    );
+
+    for section in sections.iter() {
+        let suite = escape_markup(&format!("champion {} {}", section.name, section.version));
+        doc.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\">\n",
+            suite,
+            section.battles.len(),
+            failures(&section.battles)
+        ));
+
+        for battle in section.battles.iter() {
+            let case = format!(
+                "    <testcase name=\"{}\" classname=\"{}\"",
+                escape_markup(&battle.metric),
+                suite
+            );
+            match battle.outcome {
+                Outcome::Loss => {}
+                Outcome::Skipped => {
+                    doc.push_str(&format!("{}>\n      <skipped/>\n    </testcase>\n", case));
+                    continue;
+                }
+                _ => {
+                    doc.push_str(&format!("{}/>\n", case));
+                    continue;
+                }
+            }
+
+            let direction = if battle.lower_is_better {
+                "lower"
+            } else {
+                "higher"
+            };
+            let message = format!(
+                "{} regressed: champion {}, challenger {} ({} is better)",
+                battle.metric,
+                value(&battle.report.champion_metric),
+                value(&battle.report.challenger_metric),
+                direction
+            );
+            doc.push_str(&format!(
+                "{}>\n      <failure message=\"{}\" type=\"loss\"/>\n    </testcase>\n",
+                case,
+                escape_markup(&message)
+            ));
+        }
+        doc.push_str("  </testsuite>\n");
+    }
+
+    doc.push_str("</testsuites>\n");
+    doc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::metrics::CompareSummary;
+
+    fn report(version: &str, champion: Option<f64>, challenger: f64) -> types::BattleReport {
+        let metric = |value: f64| types::Metric {
+            name: "mae".to_string(),
+            value: value.into(),
+            step: None,
+            timestamp: None,
+        };
+
+        types::BattleReport {
+            champion_name: "model".to_string(),
+            champion_version: version.to_string(),
+            champion_metric: champion.map(metric),
+            challenger_metric: Some(metric(challenger)),
+            challenger_win: false,
+        }
+    }
+
+    #[test]
+    fn test_render_report() {
+        let reports = [
+            report("1.1.0", Some(5.0), 4.0),
+            report("1.0.0", Some(2.0), 4.0),
+            report("1.0.1", None, 4.0),
+        ];
+        let outcomes = [Outcome::Win, Outcome::Loss, Outcome::Loss];
+        let battles: Vec<Battle> = reports
+            .iter()
+            .zip(outcomes)
+            .map(|(report, outcome)| Battle {
+                metric: "mae".to_string(),
+                report,
+                outcome,
+                lower_is_better: true,
+            })
+            .collect();
+        let summary = CompareSummary::from_battles(&battles);
+        let result = CompareResult {
+            challenger_name: "model",
+            challenger_version: "1.2.0",
+            battles: &battles,
+            summary: &summary,
+            qualified: false,
+        };
+
+        let markdown = render_report(ReportFormat::Markdown, &result);
+        assert!(markdown.starts_with("# Metric comparison: model 1.2.0\n"));
+        assert!(markdown.contains("| mae | min | 5.0 | 4.0 | -1 | -20% | +20% | ✅ win |"));
+        assert!(markdown.contains("| mae | min | missing | 4.0 | - | - | - | ❌ loss |"));
+        assert!(markdown.contains("- Champion model 1.0.1 is missing: mae"));
+        assert!(markdown.ends_with(
+            "Challenger model 1.2.0: 1 wins, 2 losses, 0 within tolerance, 0 skipped - ❌ **not qualified**\n"
+        ));
+
+        // champion sections are ordered by version
+        let first = markdown.find("## Champion model 1.0.0").unwrap();
+        let last = markdown.find("## Champion model 1.1.0").unwrap();
+        assert!(first < last);
+
+        let html = render_report(ReportFormat::Html, &result);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<span class=\"badge win\">win</span>"));
+        assert!(html.contains("<td class=\"missing\">missing</td>"));
+        assert!(html.contains("<span class=\"badge loss\">not qualified</span>"));
+
+        // reports never carry terminal colour codes
+        assert!(!markdown.contains('\u{1b}') && !html.contains('\u{1b}'));
+
+        let junit = render_junit(&result);
+        assert!(junit.contains("<testsuites name=\"model 1.2.0\" tests=\"3\" failures=\"2\">"));
+        assert!(junit.contains(
+            "<testsuite name=\"champion model 1.1.0\" tests=\"1\" failures=\"0\">\n    <testcase name=\"mae\" classname=\"champion model 1.1.0\"/>"
+        ));
+        assert!(junit.contains(
+            "<failure message=\"mae regressed: champion 2.0, challenger 4.0 (lower is better)\" type=\"loss\"/>"
+        ));
+        assert!(junit.contains("champion missing, challenger 4.0"));
+    }
+}
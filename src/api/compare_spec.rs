@@ -0,0 +1,354 @@
+use crate::api::command_structs::CompareMetricArgs;
+use crate::api::resolve::ModelSelector;
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::Path;
+use std::str::FromStr;
+use std::{fmt, fs};
+
+/// Direction in which a metric improves
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Min,
+    Max,
+}
+
+impl Direction {
+    pub fn lower_is_better(&self) -> bool {
+        *self == Direction::Min
+    }
+}
+
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "min" => Ok(Direction::Min),
+            "max" => Ok(Direction::Max),
+            _ => Err(format!("Invalid direction {}, expected min or max", value)),
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Direction::Min => write!(f, "min"),
+            Direction::Max => write!(f, "max"),
+        }
+    }
+}
+
+/// How a battle against a champion that did not log a metric is counted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum MissingPolicy {
+    /// Skip the battle, counting it as neither a win nor a loss
+    Skip,
+    /// Warn and count the battle as a challenger loss
+    #[default]
+    Warn,
+    /// Abort the comparison with an error
+    Fail,
+}
+
+/// Metric to compare
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MetricSpec {
+    pub name: String,
+    #[serde(default = "default_direction")]
+    pub direction: Direction,
+    /// Percent the challenger may be worse by
+    pub tolerance: Option<f64>,
+}
+
+/// Direction of metrics given without one
+pub fn default_direction() -> Direction {
+    Direction::Min
+}
+
+/// Comparison rules, from a spec file and/or the command line
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CompareSpec {
+    pub challenger: Option<ModelSelector>,
+    #[serde(default)]
+    pub champions: Vec<ModelSelector>,
+    #[serde(default)]
+    pub metrics: Vec<MetricSpec>,
+    #[serde(default)]
+    pub fail_on_loss: bool,
+    pub require_wins: Option<usize>,
+    #[serde(default)]
+    pub missing: MissingPolicy,
+}
+
+impl CompareSpec {
+    pub fn metric_names(&self) -> Vec<String> {
+        self.metrics.iter().map(|m| m.name.clone()).collect()
+    }
+
+    pub fn lower_is_better(&self) -> Vec<bool> {
+        self.metrics
+            .iter()
+            .map(|m| m.direction.lower_is_better())
+            .collect()
+    }
+
+    /// Checks the spec is complete and consistent before it is sent to the server
+    pub fn validate(&self) -> Result<(), String> {
+        if self.challenger.is_none() {
+            return Err("No challenger specified".to_string());
+        }
+
+        if self.champions.is_empty() {
+            return Err("No champions specified".to_string());
+        }
+
+        if self.metrics.is_empty() {
+            return Err("No metrics specified".to_string());
+        }
+
+        let mut names = HashSet::new();
+        for metric in self.metrics.iter() {
+            if metric.name.is_empty() {
+                return Err("Metric names must not be empty".to_string());
+            }
+
+            if !names.insert(&metric.name) {
+                return Err(format!(
+                    "Metric {} is specified more than once",
+                    metric.name
+                ));
+            }
+
+            if let Some(tolerance) = metric.tolerance {
+                if !tolerance.is_finite() || tolerance < 0.0 {
+                    return Err(format!(
+                        "Tolerance for {} must be a non-negative percent",
+                        metric.name
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Loads a comparison spec from a yaml or toml file
+///
+/// * `path` - path to the spec file
+pub fn load_compare_spec(path: &str) -> Result<CompareSpec, String> {
+    let data = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read comparison spec {}: {}", path, e))?;
+
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default();
+
+    let spec = match extension {
+        "yaml" | "yml" => serde_yaml::from_str(&data).map_err(|e| e.to_string()),
+        "toml" => toml::from_str(&data).map_err(|e| e.to_string()),
+        _ => {
+            return Err(format!(
+                "Unsupported comparison spec {}, expected .yaml, .yml or .toml",
+                path
+            ))
+        }
+    };
+
+    spec.map_err(|e| format!("Failed to parse comparison spec {}: {}", path, e))
+}
+
+/// Parses a metric argument of the form `name` or `name:direction`
+///
+/// * `value` - metric argument (e.g. mae:min)
+pub fn parse_metric_arg(value: &str) -> Result<(String, Option<Direction>), String> {
+    match value.split_once(':') {
+        Some((name, direction)) => Ok((name.to_string(), Some(direction.parse()?))),
+        None => Ok((value.to_string(), None)),
+    }
+}
+
+/// Builds a comparison spec from an optional spec file and command line arguments
+///
+/// Command line metrics are added to the spec file metrics, and a command line
+/// challenger or require-wins replaces the spec file value.
+///
+/// # Arguments
+///
+/// * `args` - compare-model-metrics arguments
+pub fn build_compare_spec(args: &CompareMetricArgs) -> Result<CompareSpec, String> {
+    let mut spec = match &args.spec {
+        Some(path) => load_compare_spec(path)?,
+        None => CompareSpec::default(),
+    };
+
+    if !args.lower_is_better.is_empty() && args.lower_is_better.len() != args.metric_name.len() {
+        return Err(format!(
+            "Got {} --lower-is-better values for {} --metric-name values",
+            args.lower_is_better.len(),
+            args.metric_name.len()
+        ));
+    }
+
+    for (i, metric) in args.metric_name.iter().enumerate() {
+        let (name, direction) = parse_metric_arg(metric)?;
+        let lower_is_better = args.lower_is_better.get(i);
+
+        let direction = match (direction, lower_is_better) {
+            (Some(direction), Some(lower)) if direction.lower_is_better() != *lower => {
+                return Err(format!(
+                    "Direction {} for {} conflicts with --lower-is-better {}",
+                    direction, name, lower
+                ));
+            }
+            (Some(direction), _) => direction,
+            (None, Some(false)) => Direction::Max,
+            (None, _) => default_direction(),
+        };
+
+        spec.metrics.push(MetricSpec {
+            name,
+            direction,
+            tolerance: None,
+        });
+    }
+
+    for (name, tolerance) in args.tolerance.iter() {
+        let metric = spec
+            .metrics
+            .iter_mut()
+            .find(|m| &m.name == name)
+            .ok_or(format!("Tolerance given for unknown metric {}", name))?;
+        metric.tolerance = Some(*tolerance);
+    }
+
+    if let Some(challenger) = &args.challenger {
+        spec.challenger = Some(challenger.clone());
+    } else if let Some(uid) = &args.challenger_uid {
+        spec.challenger = Some(ModelSelector::Uid(uid.clone()));
+    }
+
+    spec.champions.extend(
+        args.champion_uid
+            .iter()
+            .map(|uid| ModelSelector::Uid(uid.clone()))
+            .chain(args.champion.iter().cloned()),
+    );
+
+    spec.fail_on_loss = spec.fail_on_loss || args.fail_on_loss;
+    if args.require_wins.is_some() {
+        spec.require_wins = args.require_wins;
+    }
+
+    if let Some(missing) = args.missing {
+        spec.missing = missing;
+    }
+
+    spec.validate()?;
+
+    Ok(spec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_metric_arg() {
+        assert_eq!(
+            parse_metric_arg("mae:min").unwrap(),
+            ("mae".to_string(), Some(Direction::Min))
+        );
+        assert_eq!(
+            parse_metric_arg("auc:max").unwrap(),
+            ("auc".to_string(), Some(Direction::Max))
+        );
+        assert_eq!(parse_metric_arg("mae").unwrap(), ("mae".to_string(), None));
+        assert!(parse_metric_arg("mae:lowest").is_err());
+    }
+
+    #[test]
+    fn test_load_compare_spec() {
+        let yaml = concat!(
+            "challenger: model@latest\n",
+            "champions: [model@last:3, 'tag:stage=prod']\n",
+            "fail_on_loss: true\n",
+            "missing: skip\n",
+            "metrics:\n",
+            "  - name: mae\n",
+            "    direction: min\n",
+            "    tolerance: 1.0\n",
+            "  - name: auc\n",
+            "    direction: max\n",
+        );
+        let toml = concat!(
+            "challenger = \"model@latest\"\n",
+            "champions = [\"model@last:3\", \"tag:stage=prod\"]\n",
+            "fail_on_loss = true\n",
+            "missing = \"skip\"\n",
+            "[[metrics]]\n",
+            "name = \"mae\"\n",
+            "direction = \"min\"\n",
+            "tolerance = 1.0\n",
+            "[[metrics]]\n",
+            "name = \"auc\"\n",
+            "direction = \"max\"\n",
+        );
+
+        let dir = std::env::temp_dir();
+        for (filename, data) in [("compare_spec.yaml", yaml), ("compare_spec.toml", toml)] {
+            let path = dir.join(filename);
+            fs::write(&path, data).unwrap();
+
+            let spec = load_compare_spec(path.to_str().unwrap()).unwrap();
+            spec.validate().unwrap();
+            assert_eq!(spec.metric_names(), vec!["mae", "auc"]);
+            assert_eq!(spec.lower_is_better(), vec![true, false]);
+            assert_eq!(spec.metrics[0].tolerance, Some(1.0));
+            assert_eq!(spec.champions.len(), 2);
+            assert!(spec.fail_on_loss);
+            assert_eq!(spec.missing, MissingPolicy::Skip);
+
+            fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_validate_compare_spec() {
+        let metric = MetricSpec {
+            name: "mae".to_string(),
+            direction: Direction::Min,
+            tolerance: None,
+        };
+        let mut spec = CompareSpec {
+            challenger: Some(ModelSelector::Uid("challenger".to_string())),
+            champions: vec![ModelSelector::Uid("champion".to_string())],
+            metrics: vec![metric.clone()],
+            fail_on_loss: false,
+            require_wins: None,
+            missing: MissingPolicy::Warn,
+        };
+        assert!(spec.validate().is_ok());
+
+        spec.metrics.push(metric);
+        assert_eq!(
+            spec.validate().unwrap_err(),
+            "Metric mae is specified more than once"
+        );
+
+        spec.metrics.pop();
+        spec.metrics[0].tolerance = Some(-1.0);
+        assert!(spec.validate().is_err());
+
+        spec.metrics[0].tolerance = None;
+        spec.champions.clear();
+        assert_eq!(spec.validate().unwrap_err(), "No champions specified");
+    }
+}
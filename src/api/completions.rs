@@ -0,0 +1,333 @@
+use crate::api::list_cards::get_registry;
+use crate::api::types;
+use crate::api::utils::{remove_suffix, OpsmlPaths};
+use clap::{Command, ValueEnum};
+use clap_complete::env::Shells;
+use clap_complete::CompletionCandidate;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Environment variable the shell scripts set when asking for completions
+pub const COMPLETE_VAR: &str = "COMPLETE";
+
+/// Seconds fetched cards are reused for completions
+const CARD_CACHE_TTL: u64 = 60;
+
+/// Time allowed for fetching cards, so a slow server does not block the shell
+const CARD_REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Registries cards can be listed from
+const REGISTRIES: [&str; 5] = ["data", "model", "run", "pipeline", "audit"];
+
+/// Shell to generate completions for
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+/// Cards as cached for completions
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedCards {
+    key: String,
+    cards: Vec<types::Card>,
+    /// Seconds since the unix epoch
+    checked_at: u64,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Value of an option already typed on the command line being completed
+///
+/// * `args` - command line
+/// * `flag` - option name, e.g. --name
+fn typed_option(args: &[String], flag: &str) -> Option<String> {
+    let prefix = format!("{}=", flag);
+
+    args.iter()
+        .enumerate()
+        .rev()
+        .find_map(|(i, arg)| match arg.strip_prefix(&prefix) {
+            Some(value) => Some(value.to_string()),
+            None if arg == flag => args.get(i + 1).cloned(),
+            None => None,
+        })
+        .filter(|value| !value.is_empty() && !value.starts_with('-'))
+}
+
+/// Cache file for a card request
+///
+/// * `dir` - cache directory
+/// * `key` - tracking uri, registry and name
+fn cache_path(dir: &Path, key: &str) -> PathBuf {
+    let hash = format!("{:x}", Sha256::digest(key.as_bytes()));
+    dir.join(format!("cards-{}.json", &hash[..16]))
+}
+
+/// Reads cached cards that have not expired
+///
+/// * `path` - cache file
+/// * `key` - tracking uri, registry and name
+/// * `now` - seconds since the unix epoch
+fn read_cache(path: &Path, key: &str, now: u64) -> Option<Vec<types::Card>> {
+    let data = fs::read_to_string(path).ok()?;
+    let cached: CachedCards = serde_json::from_str(&data).ok()?;
+
+    if cached.key != key || now.saturating_sub(cached.checked_at) >= CARD_CACHE_TTL {
+        return None;
+    }
+
+    Some(cached.cards)
+}
+
+/// Lists cards from the server, reusing recent results
+///
+/// Completions never fail, so any error or unknown registry returns no cards.
+///
+/// * `registry` - registry to list cards from
+/// * `name` - card name
+fn fetch_cards(registry: &str, name: Option<String>) -> Vec<types::Card> {
+    if !REGISTRIES.contains(&registry) {
+        return Vec::new();
+    }
+
+    let uri = match std::env::var("OPSML_TRACKING_URI") {
+        Ok(uri) => remove_suffix(&uri, "/"),
+        Err(_) => return Vec::new(),
+    };
+
+    let key = format!(
+        "{} {} {}",
+        uri,
+        registry,
+        name.as_deref().unwrap_or_default()
+    );
+    let path = cache_path(&std::env::temp_dir().join("opsml-cli"), &key);
+    if let Some(cards) = read_cache(&path, &key, now()) {
+        return cards;
+    }
+
+    let request = types::ListTableRequest {
+        table_name: get_registry(registry),
+        name,
+        team: None,
+        version: None,
+        uid: None,
+        limit: None,
+        tags: None,
+        max_date: None,
+    };
+
+    let cards = reqwest::blocking::Client::builder()
+        .timeout(CARD_REQUEST_TIMEOUT)
+        .build()
+        .and_then(|client| {
+            client
+                .post(OpsmlPaths::ListCard.url(&uri))
+                .json(&request)
+                .send()
+        })
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.json::<types::ListCardResponse>());
+
+    match cards {
+        Ok(response) => {
+            let cached = CachedCards {
+                key,
+                cards: response.cards,
+                checked_at: now(),
+            };
+            let _ = fs::create_dir_all(path.parent().unwrap_or(Path::new(".")))
+                .and_then(|_| fs::write(&path, serde_json::to_string(&cached).unwrap_or_default()));
+            cached.cards
+        }
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Candidates starting with the text typed so far, without duplicates
+///
+/// * `values` - possible values
+/// * `current` - text typed so far
+fn candidates(values: Vec<String>, current: &OsStr) -> Vec<CompletionCandidate> {
+    let current = current.to_string_lossy();
+    let mut seen = Vec::new();
+
+    for value in values {
+        if value.starts_with(current.as_ref()) && !seen.contains(&value) {
+            seen.push(value);
+        }
+    }
+
+    seen.into_iter().map(CompletionCandidate::new).collect()
+}
+
+fn command_line() -> Vec<String> {
+    std::env::args().collect()
+}
+
+/// Registry typed with --registry, models by default
+///
+/// * `args` - command line
+fn typed_registry(args: &[String]) -> String {
+    typed_option(args, "--registry").unwrap_or("model".to_string())
+}
+
+/// Completes registry names
+///
+/// * `current` - text typed so far
+pub fn complete_registry(current: &OsStr) -> Vec<CompletionCandidate> {
+    candidates(REGISTRIES.iter().map(|r| r.to_string()).collect(), current)
+}
+
+/// Completes card names from the registry typed with --registry, models by default
+///
+/// * `current` - text typed so far
+pub fn complete_card_name(current: &OsStr) -> Vec<CompletionCandidate> {
+    let names = fetch_cards(&typed_registry(&command_line()), None)
+        .into_iter()
+        .map(|card| card.name)
+        .collect();
+
+    candidates(names, current)
+}
+
+/// Completes versions of the card typed with --name
+///
+/// * `current` - text typed so far
+pub fn complete_version(current: &OsStr) -> Vec<CompletionCandidate> {
+    let args = command_line();
+    let mut cards = fetch_cards(&typed_registry(&args), typed_option(&args, "--name"));
+
+    cards.sort_by(|a, b| {
+        match (
+            semver::Version::parse(&a.version),
+            semver::Version::parse(&b.version),
+        ) {
+            (Ok(a), Ok(b)) => b.cmp(&a),
+            _ => b.version.cmp(&a.version),
+        }
+    });
+
+    candidates(
+        cards.into_iter().map(|card| card.version).collect(),
+        current,
+    )
+}
+
+/// Prints the script registering shell completions
+///
+/// The script calls back into the cli for candidates, so card names and versions
+/// are completed from the server.
+///
+/// # Arguments
+///
+/// * `cmd` - Cli definition
+/// * `shell` - Shell to register completions for
+pub fn completions(cmd: Command, shell: CompletionShell) -> Result<(), String> {
+    let name = cmd.get_name().to_string();
+    let shell_name = shell.to_possible_value().unwrap().get_name().to_string();
+
+    let shells = Shells::builtins();
+    let completer = shells
+        .completer(&shell_name)
+        .ok_or(format!("Unsupported shell {}", shell_name))?;
+
+    completer
+        .write_registration(COMPLETE_VAR, &name, &name, &name, &mut io::stdout())
+        .map_err(|e| format!("Failed to write completions: {}", e))
+}
+
+/// Renders man pages for the cli and its subcommands
+///
+/// Prints the top level page when no directory is given.
+///
+/// # Arguments
+///
+/// * `cmd` - Cli definition
+/// * `out_dir` - Directory to write a page per command to
+pub fn man(cmd: Command, out_dir: Option<&str>) -> Result<(), String> {
+    match out_dir {
+        Some(out_dir) => {
+            fs::create_dir_all(out_dir)
+                .map_err(|e| format!("Failed to create {}: {}", out_dir, e))?;
+            clap_mangen::generate_to(cmd, out_dir)
+                .map_err(|e| format!("Failed to write man pages: {}", e))?;
+            println!("Wrote man pages to {}", out_dir);
+            Ok(())
+        }
+        None => clap_mangen::Man::new(cmd)
+            .render(&mut io::stdout())
+            .map_err(|e| format!("Failed to render man page: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typed_option() {
+        let args: Vec<String> = [
+            "opsml-cli",
+            "--",
+            "opsml-cli",
+            "list-cards",
+            "--registry=data",
+            "--name",
+            "model",
+            "--version",
+            "",
+        ]
+        .iter()
+        .map(|a| a.to_string())
+        .collect();
+
+        assert_eq!(typed_option(&args, "--registry").as_deref(), Some("data"));
+        assert_eq!(typed_option(&args, "--name").as_deref(), Some("model"));
+        assert_eq!(typed_option(&args, "--version"), None);
+        assert_eq!(typed_option(&args, "--uid"), None);
+
+        // unknown registries are not sent to the server
+        assert!(fetch_cards("models", None).is_empty());
+
+        let values = candidates(
+            vec!["model".to_string(), "mock".to_string(), "model".to_string()],
+            OsStr::new("mo"),
+        );
+        assert_eq!(values.len(), 2);
+    }
+
+    #[test]
+    fn test_card_cache() {
+        let dir = std::env::temp_dir().join("opsml_completion_cache_test");
+        let key = "http://localhost model";
+        let path = cache_path(&dir, key);
+        fs::create_dir_all(&dir).unwrap();
+
+        let cached = CachedCards {
+            key: key.to_string(),
+            cards: Vec::new(),
+            checked_at: 100,
+        };
+        fs::write(&path, serde_json::to_string(&cached).unwrap()).unwrap();
+
+        assert!(read_cache(&path, key, 110).is_some());
+        assert!(read_cache(&path, key, 100 + CARD_CACHE_TTL).is_none());
+        assert!(read_cache(&path, "http://localhost data", 110).is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,243 @@
+use crate::api::metrics::{fetch_model_metrics, format_signed, MetricFilter};
+use crate::api::output::{render_csv, render_table, OutputFormat};
+use crate::api::resolve::{resolve_cards, ModelSelector};
+use crate::api::types;
+use crate::api::utils;
+use owo_colors::OwoColorize;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+/// Which model logged a metric
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Presence {
+    Both,
+    A,
+    B,
+}
+
+/// Difference of a single metric between two models
+#[derive(Debug, PartialEq, Serialize)]
+struct MetricDiff {
+    metric: String,
+    a: Option<Value>,
+    b: Option<Value>,
+    /// b minus a
+    delta: Option<f64>,
+    /// Delta relative to a (percent)
+    change: Option<f64>,
+    presence: Presence,
+}
+
+impl MetricDiff {
+    fn to_record(&self, color: bool) -> Vec<String> {
+        let value = |value: &Option<Value>| match value {
+            Some(value) => value.to_string(),
+            None => "-".to_string(),
+        };
+
+        let presence = match self.presence {
+            Presence::Both => "both".to_string(),
+            Presence::A if color => "a only".yellow().to_string(),
+            Presence::B if color => "b only".yellow().to_string(),
+            Presence::A => "a only".to_string(),
+            Presence::B => "b only".to_string(),
+        };
+
+        vec![
+            self.metric.clone(),
+            value(&self.a),
+            value(&self.b),
+            self.delta.map_or("-".to_string(), format_signed),
+            self.change.map_or("-".to_string(), |change| {
+                format!("{}%", format_signed(change))
+            }),
+            presence,
+        ]
+    }
+}
+
+/// Diffs every metric logged by either model
+///
+/// Metrics logged at multiple steps are compared at their last step.
+///
+/// * `a` - metrics logged by the first model
+/// * `b` - metrics logged by the second model
+fn diff_metrics_response(
+    a: types::ListMetricResponse,
+    b: types::ListMetricResponse,
+) -> Vec<MetricDiff> {
+    let filter = MetricFilter::default();
+    let (a, b) = (filter.apply(a), filter.apply(b));
+
+    let names: BTreeSet<&String> = a.metrics.keys().chain(b.metrics.keys()).collect();
+    let last = |metrics: &types::ListMetricResponse, name: &str| {
+        metrics
+            .metrics
+            .get(name)
+            .and_then(|values| values.last())
+            .map(|metric| metric.value.clone())
+    };
+
+    names
+        .into_iter()
+        .map(|name| {
+            let a_value = last(&a, name);
+            let b_value = last(&b, name);
+
+            let numbers = a_value
+                .as_ref()
+                .and_then(|v| v.as_f64())
+                .zip(b_value.as_ref().and_then(|v| v.as_f64()));
+            let delta = numbers.map(|(a, b)| b - a);
+            let change = numbers
+                .filter(|(a, _)| *a != 0.0)
+                .map(|(a, b)| (b - a) / a.abs() * 100.0);
+
+            let presence = match (&a_value, &b_value) {
+                (Some(_), None) => Presence::A,
+                (None, Some(_)) => Presence::B,
+                _ => Presence::Both,
+            };
+
+            MetricDiff {
+                metric: name.clone(),
+                a: a_value,
+                b: b_value,
+                delta,
+                change,
+                presence,
+            }
+        })
+        .collect()
+}
+
+/// Resolves a selector that must refer to a single model card
+///
+/// * `selector` - model selector
+async fn resolve_card(selector: &ModelSelector) -> Result<types::Card, String> {
+    let mut cards = resolve_cards(selector).await?;
+
+    if cards.len() != 1 {
+        return Err(format!(
+            "Expected a single model for {}, found {}",
+            selector,
+            cards.len()
+        ));
+    }
+
+    Ok(cards.remove(0))
+}
+
+/// Shows every metric logged by either of two models
+///
+/// # Arguments
+///
+/// * `a` - First model
+/// * `b` - Second model
+/// * `format` - Output format
+#[tokio::main]
+pub async fn diff_metrics(
+    a: &ModelSelector,
+    b: &ModelSelector,
+    format: OutputFormat,
+) -> Result<(), utils::RequestError> {
+    let a_card = resolve_card(a).await?;
+    let b_card = resolve_card(b).await?;
+
+    let request = |card: &types::Card| types::CardRequest {
+        name: None,
+        version: None,
+        uid: Some(card.uid.clone()),
+    };
+    let a_metrics = fetch_model_metrics(&request(&a_card)).await?;
+    let b_metrics = fetch_model_metrics(&request(&b_card)).await?;
+
+    let diffs = diff_metrics_response(a_metrics, b_metrics);
+    let a_label = format!("a: {} {}", a_card.name, a_card.version);
+    let b_label = format!("b: {} {}", b_card.name, b_card.version);
+    let header = [
+        "Metric",
+        a_label.as_str(),
+        b_label.as_str(),
+        "Delta",
+        "Change",
+        "Present",
+    ];
+
+    match format {
+        OutputFormat::Table => {
+            let records: Vec<Vec<String>> = diffs.iter().map(|d| d.to_record(true)).collect();
+            println!("{}", render_table(&header, &records));
+        }
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&diffs).map_err(|e| e.to_string())?
+        ),
+        OutputFormat::Csv => {
+            let records: Vec<Vec<String>> = diffs.iter().map(|d| d.to_record(false)).collect();
+            print!("{}", render_csv(&header, &records)?);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn metrics(values: &[(&str, Option<i64>, Value)]) -> types::ListMetricResponse {
+        let mut metrics: HashMap<String, Vec<types::Metric>> = HashMap::new();
+        for (name, step, value) in values.iter() {
+            metrics
+                .entry(name.to_string())
+                .or_default()
+                .push(types::Metric {
+                    name: name.to_string(),
+                    value: value.clone(),
+                    step: step.map(|step| step.into()),
+                    timestamp: None,
+                });
+        }
+        types::ListMetricResponse { metrics }
+    }
+
+    #[test]
+    fn test_diff_metrics_response() {
+        let a = metrics(&[
+            ("mae", None, 4.into()),
+            ("loss", Some(1), 0.5.into()),
+            ("loss", Some(0), 1.0.into()),
+            ("auc", None, 0.8.into()),
+        ]);
+        let b = metrics(&[
+            ("mae", None, 5.into()),
+            ("loss", Some(0), 0.25.into()),
+            ("optimizer", None, "adam".into()),
+        ]);
+
+        let diffs = diff_metrics_response(a, b);
+        let names: Vec<&str> = diffs.iter().map(|d| d.metric.as_str()).collect();
+        assert_eq!(names, vec!["auc", "loss", "mae", "optimizer"]);
+
+        assert_eq!(diffs[0].presence, Presence::A);
+        assert_eq!(diffs[0].delta, None);
+
+        // multi-step metrics are compared at their last step
+        assert_eq!(diffs[1].a, Some(0.5.into()));
+        assert_eq!(diffs[1].delta, Some(-0.25));
+        assert_eq!(diffs[1].change, Some(-50.0));
+
+        assert_eq!(
+            diffs[2].to_record(false),
+            vec!["mae", "4", "5", "+1", "+25%", "both"]
+        );
+        assert_eq!(
+            diffs[3].to_record(false),
+            vec!["optimizer", "-", "\"adam\"", "-", "-", "b only"]
+        );
+    }
+}
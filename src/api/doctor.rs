@@ -0,0 +1,622 @@
+use crate::api::list_cards::get_registry;
+use crate::api::server::check_version;
+use crate::api::types;
+use crate::api::utils::{remove_suffix, OpsmlPaths};
+use owo_colors::OwoColorize;
+use reqwest::{Client, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::error::Error;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use std::{env, fmt};
+
+/// Time allowed for each network check
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Hint for routes that are missing or answer with an unexpected shape
+const API_MISMATCH_HINT: &str = "The server may run an opsml version this cli does not support";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CheckStatus {
+    Pass,
+    Fail,
+    Skip,
+}
+
+/// Result of a single diagnostic
+#[derive(Debug)]
+struct Check {
+    name: String,
+    status: CheckStatus,
+    detail: String,
+    hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &str, detail: impl Into<String>) -> Self {
+        Check {
+            name: name.to_string(),
+            status: CheckStatus::Pass,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn fail(name: &str, detail: impl Into<String>, hint: &str) -> Self {
+        Check {
+            name: name.to_string(),
+            status: CheckStatus::Fail,
+            detail: detail.into(),
+            hint: Some(hint.to_string()),
+        }
+    }
+
+    fn skip(name: &str, detail: impl Into<String>) -> Self {
+        Check {
+            name: name.to_string(),
+            status: CheckStatus::Skip,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = format!("{:<30}", self.name);
+        match self.status {
+            CheckStatus::Pass => write!(f, "{} {} {}", "✔".green(), name, self.detail)?,
+            CheckStatus::Fail => write!(f, "{} {} {}", "✘".red(), name, self.detail.red())?,
+            CheckStatus::Skip => write!(f, "{} {} {}", "-".yellow(), name, self.detail)?,
+        }
+
+        if let Some(hint) = &self.hint {
+            write!(f, "\n  {} {}", "hint:".bold(), hint)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Formats an error with the errors that caused it
+///
+/// * `error` - error to format
+fn error_chain(error: &dyn Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+
+    while let Some(cause) = source {
+        // some errors already include their cause in their message
+        let cause_message = cause.to_string();
+        if !message.contains(&cause_message) {
+            message.push_str(&format!(": {}", cause_message));
+        }
+        source = cause.source();
+    }
+
+    message
+}
+
+/// Checks the tracking uri is set and is an http(s) url
+///
+/// * `value` - value of OPSML_TRACKING_URI
+fn check_config(value: Option<String>) -> (Check, Option<Url>) {
+    const NAME: &str = "configuration";
+
+    let value = match value {
+        Some(value) if !value.is_empty() => remove_suffix(&value, "/"),
+        _ => {
+            return (
+                Check::fail(
+                    NAME,
+                    "OPSML_TRACKING_URI is not set",
+                    "export OPSML_TRACKING_URI=https://<your opsml server>",
+                ),
+                None,
+            )
+        }
+    };
+
+    match Url::parse(&value) {
+        Ok(url) if ["http", "https"].contains(&url.scheme()) && url.host().is_some() => (
+            Check::pass(NAME, format!("OPSML_TRACKING_URI={}", value)),
+            Some(url),
+        ),
+        Ok(_) => (
+            Check::fail(
+                NAME,
+                format!("OPSML_TRACKING_URI={} is not an http(s) url", value),
+                "Use a full url such as https://opsml.example.com",
+            ),
+            None,
+        ),
+        Err(e) => (
+            Check::fail(
+                NAME,
+                format!("OPSML_TRACKING_URI={} is not a valid url: {}", value, e),
+                "Use a full url such as https://opsml.example.com",
+            ),
+            None,
+        ),
+    }
+}
+
+/// Resolves the server host name
+///
+/// * `url` - tracking uri
+fn check_dns(url: &Url) -> (Check, Vec<SocketAddr>) {
+    const NAME: &str = "dns";
+    let host = url.host_str().unwrap_or_default();
+    let port = url.port_or_known_default().unwrap_or(80);
+
+    match (host, port).to_socket_addrs() {
+        Ok(addrs) => {
+            let addrs: Vec<SocketAddr> = addrs.collect();
+            let ips: Vec<String> = addrs.iter().take(3).map(|a| a.ip().to_string()).collect();
+            (
+                Check::pass(NAME, format!("{} resolves to {}", host, ips.join(", "))),
+                addrs,
+            )
+        }
+        Err(e) => (
+            Check::fail(
+                NAME,
+                format!("Failed to resolve {}: {}", host, e),
+                "Check the host name and your dns or vpn settings",
+            ),
+            Vec::new(),
+        ),
+    }
+}
+
+/// Opens a tcp connection to the server
+///
+/// * `addrs` - resolved server addresses
+fn check_tcp(addrs: &[SocketAddr]) -> Check {
+    const NAME: &str = "tcp connect";
+    let mut errors = Vec::new();
+
+    for addr in addrs.iter() {
+        match TcpStream::connect_timeout(addr, PROBE_TIMEOUT) {
+            Ok(_) => return Check::pass(NAME, format!("connected to {}", addr)),
+            Err(e) => errors.push(format!("{}: {}", addr, e)),
+        }
+    }
+
+    Check::fail(
+        NAME,
+        format!("Failed to connect to {}", errors.join(", ")),
+        "Check the port, and any firewall or proxy between you and the server",
+    )
+}
+
+/// Completes a tls handshake with https servers
+///
+/// * `client` - http client
+/// * `url` - tracking uri
+async fn check_tls(client: &Client, url: &Url) -> Check {
+    const NAME: &str = "tls handshake";
+
+    if url.scheme() != "https" {
+        return Check::skip(NAME, "http uri, no tls");
+    }
+
+    match client.get(url.clone()).send().await {
+        Ok(_) => Check::pass(NAME, "certificate accepted"),
+        Err(e) => Check::fail(
+            NAME,
+            error_chain(&e),
+            "If the server uses a private certificate authority, add it to the system trust store",
+        ),
+    }
+}
+
+/// Sends a request and returns the status and body
+///
+/// * `request` - request to send
+async fn probe(request: reqwest::RequestBuilder) -> Result<(StatusCode, String), String> {
+    let response = request.send().await.map_err(|e| error_chain(&e))?;
+    let status = response.status();
+    let body = response.text().await.map_err(|e| error_chain(&e))?;
+
+    Ok((status, body))
+}
+
+/// Checks a route answers successfully with the expected json shape
+///
+/// * `name` - check name
+/// * `result` - status and body returned by the route
+fn check_shape<T: DeserializeOwned>(
+    name: &str,
+    result: Result<(StatusCode, String), String>,
+) -> (Check, Option<T>) {
+    let (status, body) = match result {
+        Ok(result) => result,
+        Err(e) => {
+            return (
+                Check::fail(name, e, "Check the server logs for this request"),
+                None,
+            )
+        }
+    };
+
+    if status == StatusCode::NOT_FOUND || status == StatusCode::METHOD_NOT_ALLOWED {
+        return (
+            Check::fail(
+                name,
+                format!("route not found ({})", status),
+                API_MISMATCH_HINT,
+            ),
+            None,
+        );
+    }
+
+    if !status.is_success() {
+        return (
+            Check::fail(
+                name,
+                format!("server answered {}", status),
+                "Check the server logs for this request",
+            ),
+            None,
+        );
+    }
+
+    match serde_json::from_str::<T>(&body) {
+        Ok(value) => (Check::pass(name, format!("{}", status)), Some(value)),
+        Err(e) => (
+            Check::fail(
+                name,
+                format!("unexpected response: {}", e),
+                API_MISMATCH_HINT,
+            ),
+            None,
+        ),
+    }
+}
+
+/// Posts json to a route and checks the response shape
+///
+/// * `client` - http client
+/// * `name` - check name
+/// * `url` - route url
+/// * `body` - request body
+async fn check_post<B: Serialize, T: DeserializeOwned>(
+    client: &Client,
+    name: &str,
+    url: &str,
+    body: &B,
+) -> (Check, Option<T>) {
+    check_shape(name, probe(client.post(url).json(body)).await)
+}
+
+/// Checks the server health, version and authentication, then every route
+///
+/// * `client` - http client
+/// * `base` - tracking uri without a trailing slash
+async fn check_api(client: &Client, base: &str) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    let (check, health) = check_shape::<Value>(
+        "health",
+        probe(client.get(OpsmlPaths::HealthCheck.url(base))).await,
+    );
+    checks.push(match health {
+        Some(health) if health["is_alive"] == Value::Bool(false) => Check::fail(
+            "health",
+            "server reports it is not alive",
+            "Check the server logs and its database and storage connections",
+        ),
+        _ => check,
+    });
+
+    let version = probe(client.get(OpsmlPaths::Version.url(base))).await;
+    let (check, version) = match version {
+        // older servers have no version route, the cli then skips the version check
+        Ok((StatusCode::NOT_FOUND, _)) => (
+            Check::pass(
+                "version",
+                "server does not report a version, assuming it is supported",
+            ),
+            None,
+        ),
+        result => check_shape::<Value>("version", result),
+    };
+    checks.push(match version.as_ref().and_then(|v| v["version"].as_str()) {
+        Some(version) => match semver::Version::parse(version).map(|v| check_version(Some(&v))) {
+            Ok(Err(e)) => Check::fail("version", e, "Upgrade the opsml server"),
+            Ok(Ok(Some(warning))) => Check::pass("version", warning),
+            _ => Check::pass("version", format!("server version {}", version)),
+        },
+        None => check,
+    });
+
+    let list_request = types::ListTableRequest {
+        table_name: get_registry("model"),
+        name: None,
+        team: None,
+        version: None,
+        uid: None,
+        limit: Some(1),
+        tags: None,
+        max_date: None,
+    };
+    let listed = probe(
+        client
+            .post(OpsmlPaths::ListCard.url(base))
+            .json(&list_request),
+    )
+    .await;
+
+    checks.push(match &listed {
+        Ok((status, _)) if *status == StatusCode::UNAUTHORIZED || *status == StatusCode::FORBIDDEN => {
+            Check::fail(
+                "authentication",
+                format!("server rejected the request ({})", status),
+                "opsml-cli does not send credentials, ask for access from the network you run it in",
+            )
+        }
+        Ok(_) => Check::pass("authentication", "no credentials required"),
+        Err(_) => Check::skip("authentication", "server unreachable"),
+    });
+
+    let (check, cards) = check_shape::<types::ListCardResponse>("route cards/list", listed);
+    checks.push(check);
+
+    let card = match cards.and_then(|cards| cards.cards.into_iter().next()) {
+        Some(card) => card,
+        None => {
+            for route in [
+                "models/metadata",
+                "models/metrics",
+                "models/compare_metrics",
+                "files/download",
+            ] {
+                checks.push(Check::skip(
+                    &format!("route {}", route),
+                    "no model card to query",
+                ));
+            }
+            return checks;
+        }
+    };
+
+    let card_request = types::CardRequest {
+        name: None,
+        version: None,
+        uid: Some(card.uid.clone()),
+    };
+
+    let (check, metadata) = check_post::<_, types::ModelMetadata>(
+        client,
+        "route models/metadata",
+        &OpsmlPaths::MetadataDownload.url(base),
+        &card_request,
+    )
+    .await;
+    checks.push(check);
+
+    let (check, metrics) = check_post::<_, types::ListMetricResponse>(
+        client,
+        "route models/metrics",
+        &OpsmlPaths::Metric.url(base),
+        &card_request,
+    )
+    .await;
+    checks.push(check);
+
+    match metrics.and_then(|metrics| metrics.metrics.keys().next().cloned()) {
+        Some(metric) => {
+            let compare_request = types::CompareMetricRequest {
+                metric_name: vec![metric],
+                lower_is_better: vec![true],
+                challenger_uid: card.uid.clone(),
+                champion_uid: vec![card.uid.clone()],
+            };
+            let (check, _) = check_post::<_, types::CompareMetricResponse>(
+                client,
+                "route models/compare_metrics",
+                &OpsmlPaths::CompareMetric.url(base),
+                &compare_request,
+            )
+            .await;
+            checks.push(check);
+        }
+        None => checks.push(Check::skip(
+            "route models/compare_metrics",
+            format!("{} {} has no metrics", card.name, card.version),
+        )),
+    }
+
+    match metadata {
+        Some(metadata) => {
+            // only the status matters, the model itself is not downloaded
+            let request = types::ModelDownloadRequest {
+                read_path: metadata.model_uri,
+            };
+            let response = client
+                .post(OpsmlPaths::Download.url(base))
+                .json(&request)
+                .send()
+                .await;
+            let result = response
+                .map(|r| (r.status(), "null".to_string()))
+                .map_err(|e| error_chain(&e));
+            checks.push(check_shape::<Value>("route files/download", result).0);
+        }
+        None => checks.push(Check::skip(
+            "route files/download",
+            "no model metadata to download from",
+        )),
+    }
+
+    checks
+}
+
+/// Runs every check, skipping those whose prerequisites failed
+async fn run_checks() -> Vec<Check> {
+    let (check, url) = check_config(env::var("OPSML_TRACKING_URI").ok());
+    let mut checks = vec![check];
+
+    let url = match url {
+        Some(url) => url,
+        None => return checks,
+    };
+
+    let (check, addrs) = check_dns(&url);
+    checks.push(check);
+    if addrs.is_empty() {
+        return checks;
+    }
+
+    let check = check_tcp(&addrs);
+    let connected = check.status == CheckStatus::Pass;
+    checks.push(check);
+    if !connected {
+        return checks;
+    }
+
+    let client = match Client::builder().timeout(PROBE_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            checks.push(Check::fail(
+                "http client",
+                error_chain(&e),
+                "Check the system tls libraries are installed",
+            ));
+            return checks;
+        }
+    };
+
+    let check = check_tls(&client, &url).await;
+    let handshake = check.status != CheckStatus::Fail;
+    checks.push(check);
+    if !handshake {
+        return checks;
+    }
+
+    let base = remove_suffix(url.as_str(), "/");
+    checks.extend(check_api(&client, &base).await);
+    checks
+}
+
+/// Diagnoses connectivity to and compatibility with the opsml server
+///
+/// Prints a checklist with hints for each failed check.
+#[tokio::main]
+pub async fn doctor() -> Result<(), String> {
+    let checks = run_checks().await;
+
+    for check in checks.iter() {
+        println!("{}", check);
+    }
+
+    let count = |status: CheckStatus| checks.iter().filter(|c| c.status == status).count();
+    let failed = count(CheckStatus::Fail);
+
+    println!(
+        "\n{} passed, {} failed, {} skipped",
+        count(CheckStatus::Pass),
+        failed,
+        count(CheckStatus::Skip)
+    );
+
+    if failed > 0 {
+        return Err(format!("{} checks failed", failed));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_config() {
+        let (check, url) = check_config(Some("http://localhost:8080/".to_string()));
+        assert_eq!(check.status, CheckStatus::Pass);
+        assert_eq!(check.detail, "OPSML_TRACKING_URI=http://localhost:8080");
+        assert_eq!(url.unwrap().port(), Some(8080));
+
+        for value in [None, Some("localhost:8080"), Some("not a url")] {
+            let (check, url) = check_config(value.map(|v| v.to_string()));
+            assert_eq!(check.status, CheckStatus::Fail, "{:?}", value);
+            assert!(check.hint.is_some());
+            assert!(url.is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_api() {
+        let mut server = mockito::Server::new_async().await;
+        let metadata = std::fs::read_to_string("./src/api/test_utils/metadata_onnx.json").unwrap();
+
+        let mocks = vec![
+            server
+                .mock("GET", "/opsml/healthcheck")
+                .with_body(r#"{"is_alive": true}"#)
+                .create_async()
+                .await,
+            server
+                .mock("GET", "/opsml/version")
+                .with_status(404)
+                .create_async()
+                .await,
+            server
+                .mock("POST", "/opsml/cards/list")
+                .with_body(
+                    r#"{"cards": [{"name": "model", "team": "team", "date": "2023-08-01",
+                    "user_email": "email", "version": "1.0.0", "uid": "uid", "tags": {}}]}"#,
+                )
+                .create_async()
+                .await,
+            server
+                .mock("POST", "/opsml/models/metadata")
+                .with_body(metadata)
+                .create_async()
+                .await,
+            server
+                .mock("POST", "/opsml/models/metrics")
+                .with_body(r#"{"metrics": {"mae": "not a list"}}"#)
+                .create_async()
+                .await,
+            server
+                .mock("POST", "/opsml/files/download")
+                .with_body("model bytes")
+                .create_async()
+                .await,
+        ];
+
+        let checks = check_api(&Client::new(), &server.url()).await;
+        let statuses: Vec<(&str, CheckStatus)> = checks
+            .iter()
+            .map(|check| (check.name.as_str(), check.status))
+            .collect();
+
+        assert_eq!(
+            statuses,
+            vec![
+                ("health", CheckStatus::Pass),
+                ("version", CheckStatus::Pass),
+                ("authentication", CheckStatus::Pass),
+                ("route cards/list", CheckStatus::Pass),
+                ("route models/metadata", CheckStatus::Pass),
+                ("route models/metrics", CheckStatus::Fail),
+                ("route models/compare_metrics", CheckStatus::Skip),
+                ("route files/download", CheckStatus::Pass),
+            ]
+        );
+        assert_eq!(
+            checks[1].detail,
+            "server does not report a version, assuming it is supported"
+        );
+        assert!(checks[5].detail.starts_with("unexpected response"));
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+    }
+}
@@ -1,38 +1,159 @@
+use crate::api::logging::{self, Level};
 use crate::api::types;
 use crate::api::utils;
 use futures_util::StreamExt;
 use reqwest::{self, Response};
+use serde::Serialize;
 use serde_json;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use std::{format, fs, path::Path};
 use tokio;
 
-const MODEL_METADATA_FILE: &str = "metadata.json";
+pub const MODEL_METADATA_FILE: &str = "metadata.json";
+
+/// How download results are reported, for use in init containers
+#[derive(Debug, Default)]
+pub struct DownloadOutput {
+    /// File written once the download succeeds
+    pub ready_file: Option<String>,
+    /// Leave out progress text, keeping only the json log line
+    pub json_log: bool,
+}
+
+/// Files fetched by a download command
+#[derive(Debug, Serialize)]
+pub struct DownloadReport {
+    pub event: &'static str,
+    pub name: String,
+    pub version: String,
+    pub team: String,
+    pub uid: Option<String>,
+    pub write_dir: String,
+    pub files: Vec<String>,
+    /// Seconds since the unix epoch
+    pub timestamp: u64,
+}
+
+impl DownloadReport {
+    fn new(
+        event: &'static str,
+        metadata: &types::ModelMetadata,
+        uid: Option<String>,
+        write_dir: &str,
+        files: Vec<String>,
+    ) -> Self {
+        DownloadReport {
+            event,
+            name: metadata.model_name.clone(),
+            version: metadata.model_version.clone(),
+            team: metadata.model_team.clone(),
+            uid,
+            write_dir: write_dir.to_string(),
+            files,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Removes a readiness file left by an earlier run
+///
+/// * `output` - download output settings
+fn clear_ready_file(output: &DownloadOutput) -> Result<(), String> {
+    if let Some(path) = &output.ready_file {
+        if Path::new(path).exists() {
+            fs::remove_file(path)
+                .map_err(|e| format!("Failed to remove ready file {}: {}", path, e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Logs a finished download and writes the readiness file
+///
+/// The readiness file holds the same json as the log line.
+///
+/// * `report` - fetched files
+/// * `output` - download output settings
+fn finish_download(report: &DownloadReport, output: &DownloadOutput) -> Result<(), String> {
+    let line = serde_json::to_string(report).map_err(|e| e.to_string())?;
+
+    // always on stderr, so log collectors see it without mixing into stdout
+    eprintln!("{}", line);
+
+    if let Some(path) = &output.ready_file {
+        create_dir_path(path);
+        fs::write(path, format!("{}\n", line))
+            .map_err(|e| format!("Failed to write ready file {}: {}", path, e))?;
+    }
+
+    Ok(())
+}
 
 /// Parses stream response
 ///
 /// * `response` - Response object
-async fn load_stream_response(response: Response) -> String {
+async fn load_stream_response(response: Response) -> Result<String, String> {
     let mut response_stream = response.bytes_stream();
     let mut stream_buffer = String::new();
     while let Some(item) = response_stream.next().await {
-        let chunk = item.unwrap();
-        let string_chunk = std::str::from_utf8(&chunk).unwrap();
+        let chunk = item.map_err(|e| format!("Failed to read response: {}", e))?;
+        let string_chunk = std::str::from_utf8(&chunk)
+            .map_err(|e| format!("Response is not valid utf-8: {}", e))?;
 
         stream_buffer.push_str(string_chunk);
     }
-    return stream_buffer;
+    Ok(stream_buffer)
 }
 
+/// Streams a download response to a file
+///
+/// Error responses are returned as an error without creating the file.
+///
+/// * `response` - download response
+/// * `filename` - file to write
 async fn download_stream_to_file(response: Response, filename: &Path) -> Result<(), String> {
+    let start = Instant::now();
+    let status = response.status().as_u16();
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(format!(
+            "Failed to download {}: {} {}",
+            filename.display(),
+            status,
+            body
+        ));
+    }
+
     let mut response_stream = response.bytes_stream();
-    let mut file = tokio::fs::File::create(filename).await.unwrap();
+    let mut file = tokio::fs::File::create(filename)
+        .await
+        .map_err(|e| format!("Failed to create {}: {}", filename.display(), e))?;
+    let mut bytes = 0;
 
     while let Some(item) = response_stream.next().await {
-        let chunk = item.unwrap();
+        let chunk =
+            item.map_err(|e| format!("Failed to download {}: {}", filename.display(), e))?;
+        bytes += chunk.len();
         tokio::io::copy(&mut chunk.as_ref(), &mut file)
             .await
-            .unwrap();
+            .map_err(|e| format!("Failed to write {}: {}", filename.display(), e))?;
     }
+
+    logging::log(
+        Level::Info,
+        "download complete",
+        &[
+            ("path", filename.display().to_string().into()),
+            ("status", status.into()),
+            ("bytes", bytes.into()),
+            ("latency_ms", (start.elapsed().as_millis() as u64).into()),
+        ],
+    );
     Ok(())
 }
 
@@ -56,17 +177,28 @@ async fn save_metadata_to_json(metadata: &types::ModelMetadata, path: &str) -> R
     Ok(())
 }
 
+/// Loads model metadata previously saved to a write directory
+///
+/// * `write_dir` - directory the metadata was written to
+pub fn load_model_metadata(write_dir: &str) -> Result<types::ModelMetadata, String> {
+    let path = format!("{}/{}", write_dir, MODEL_METADATA_FILE);
+    let data = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read model metadata {}: {}", path, e))?;
+
+    serde_json::from_str(&data).map_err(|e| format!("Failed to parse model metadata: {}", e))
+}
+
 /// Downloads a model file
-async fn download_model_file(
+pub async fn download_model_file(
     url: &str,
     model_uri: &str,
     local_save_path: &str,
-) -> Result<(), String> {
+) -> Result<(), utils::RequestError> {
     let payload = types::ModelDownloadRequest {
         read_path: model_uri.to_string(),
     };
 
-    let response = utils::make_post_request(&url, &payload).await;
+    let response = utils::make_post_request(url, &payload).await?;
     let filepath = Path::new(local_save_path);
 
     download_stream_to_file(response, filepath).await?;
@@ -75,29 +207,31 @@ async fn download_model_file(
 }
 
 /// Main function for downloading model metadata
-async fn get_model_metadata(
+pub async fn get_model_metadata(
     name: Option<String>,
     version: Option<String>,
     uid: Option<String>,
     write_dir: &str,
-) -> Result<types::ModelMetadata, String> {
+) -> Result<types::ModelMetadata, utils::RequestError> {
     let save_path: String = format!("{}/{}", write_dir, MODEL_METADATA_FILE);
 
-    let model_metadata_request = types::CardRequest {
-        name: name,
-        version: version,
-        uid: uid,
-    };
+    let model_metadata_request = types::CardRequest { name, version, uid };
 
     let response = utils::make_post_request(
-        &utils::OpsmlPaths::MetadataDownload.as_str(),
+        &utils::OpsmlPaths::MetadataDownload.as_str()?,
         &model_metadata_request,
     )
-    .await;
+    .await?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(format!("Failed to get model metadata: {} {}", status, body.trim()).into());
+    }
 
-    let loaded_response = load_stream_response(response).await;
-    let model_metadata: types::ModelMetadata =
-        serde_json::from_str(&loaded_response).expect("Failed to parse model Metadata");
+    let loaded_response = load_stream_response(response).await?;
+    let model_metadata: types::ModelMetadata = serde_json::from_str(&loaded_response)
+        .map_err(|e| format!("Failed to parse model metadata: {}", e))?;
 
     // create save path for metadata
     create_dir_path(&save_path);
@@ -131,16 +265,33 @@ fn get_model_uri(onnx: bool, model_metadata: &types::ModelMetadata) -> (String,
 /// * `version` - Version of model
 /// * `uid` - uid of model
 /// * `url` - url of opsml server
+/// * `output` - readiness file and log settings
 #[tokio::main]
 pub async fn download_model_metadata(
     name: Option<String>,
     version: Option<String>,
     uid: Option<String>,
     write_dir: &str,
-) -> Result<types::ModelMetadata, String> {
+    output: &DownloadOutput,
+) -> Result<types::ModelMetadata, utils::RequestError> {
     // check args first
     utils::check_args(&name, &version, &uid).await?;
-    let model_metadata = get_model_metadata(name, version, uid, write_dir).await?;
+    // a dry run sends nothing, so the marker of the last download stays valid
+    if !utils::dry_run() {
+        clear_ready_file(output)?;
+    }
+
+    let model_metadata = get_model_metadata(name, version, uid.clone(), write_dir).await?;
+
+    let report = DownloadReport::new(
+        "model_metadata_downloaded",
+        &model_metadata,
+        uid,
+        write_dir,
+        vec![MODEL_METADATA_FILE.to_string()],
+    );
+    finish_download(&report, output)?;
+
     Ok(model_metadata)
 }
 
@@ -154,6 +305,7 @@ pub async fn download_model_metadata(
 /// * `write_dir` - directory to write to
 /// * `no_onnx` - Flag to not download onnx model
 /// * `onnx` - Flag to download onnx model
+/// * `output` - readiness file and log settings
 #[tokio::main]
 pub async fn download_model(
     name: Option<String>,
@@ -162,18 +314,25 @@ pub async fn download_model(
     write_dir: &str,
     no_onnx: bool,
     onnx: bool,
-) -> Result<(), String> {
+    output: &DownloadOutput,
+) -> Result<(), utils::RequestError> {
     // check args first
     utils::check_args(&name, &version, &uid).await?;
+    // a dry run sends nothing, so the marker of the last download stays valid
+    if !utils::dry_run() {
+        clear_ready_file(output)?;
+    }
 
     // If no onnx is set to true, we need to cancel out onnx: true
     // Clap does not currently support command line negation flags
 
-    let download_onnx = if onnx && no_onnx { false } else { true };
-    let model_metadata = get_model_metadata(name, version, uid, write_dir).await?;
+    let download_onnx = !(onnx && no_onnx);
+    let model_metadata = get_model_metadata(name, version, uid.clone(), write_dir).await?;
     let (filename, model_uri) = get_model_uri(download_onnx, &model_metadata);
 
-    println!("Downloading model: {}, {}", filename, model_uri);
+    if !output.json_log {
+        println!("Downloading model: {}, {}", filename, model_uri);
+    }
 
     let local_save_path = format!("{}/{}", write_dir, filename);
 
@@ -182,12 +341,21 @@ pub async fn download_model(
 
     // Download model
     download_model_file(
-        &utils::OpsmlPaths::Download.as_str(),
+        &utils::OpsmlPaths::Download.as_str()?,
         &model_uri,
         &local_save_path,
     )
     .await?;
 
+    let report = DownloadReport::new(
+        "model_downloaded",
+        &model_metadata,
+        uid,
+        write_dir,
+        vec![MODEL_METADATA_FILE.to_string(), filename],
+    );
+    finish_download(&report, output)?;
+
     Ok(())
 }
 
@@ -219,7 +387,7 @@ mod tests {
         let client = reqwest::Client::new();
         let full_path: String = format!("{}/fake", &url);
         let response = client.get(&full_path).send().await.unwrap();
-        let loaded_response = load_stream_response(response).await;
+        let loaded_response = load_stream_response(response).await.unwrap();
         let model_metadata: types::ModelMetadata = serde_json::from_str(&loaded_response).unwrap();
 
         // assert structs are the same
@@ -287,9 +455,72 @@ mod tests {
         mock.assert();
         fs::remove_file(new_path).unwrap();
 
+        // error responses are not written to the file
+        let missing = server
+            .mock("GET", "/missing")
+            .with_status(404)
+            .with_body(r#"{"detail":"File not found"}"#)
+            .create();
+        let response = client
+            .get(format!("{}/missing", &url))
+            .send()
+            .await
+            .unwrap();
+
+        let error = download_stream_to_file(response, Path::new(new_path))
+            .await
+            .unwrap_err();
+        assert!(error.contains("404 Not Found"));
+        assert!(!Path::new(new_path).exists());
+        missing.assert();
+
+        // a file that cannot be created is an error rather than a panic
+        let response = client.get(&full_path).send().await.unwrap();
+        let error = download_stream_to_file(
+            response,
+            Path::new("./src/api/test_utils/missing/model.onnx"),
+        )
+        .await
+        .unwrap_err();
+        assert!(error.starts_with("Failed to create ./src/api/test_utils/missing/model.onnx"));
+
         Ok(())
     }
 
+    #[test]
+    fn test_finish_download() {
+        let data = fs::read_to_string("./src/api/test_utils/metadata_onnx.json").unwrap();
+        let metadata: types::ModelMetadata = serde_json::from_str(&data).unwrap();
+        let dir = std::env::temp_dir().join("opsml_ready_file_test");
+        let ready_file = dir.join("status").join("ready");
+        let output = DownloadOutput {
+            ready_file: Some(ready_file.to_string_lossy().to_string()),
+            json_log: true,
+        };
+
+        let report = DownloadReport::new(
+            "model_downloaded",
+            &metadata,
+            Some("uid".to_string()),
+            ".models",
+            vec!["model.onnx".to_string()],
+        );
+        finish_download(&report, &output).unwrap();
+
+        let marker: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&ready_file).unwrap()).unwrap();
+        assert_eq!(marker["event"], "model_downloaded");
+        assert_eq!(marker["name"], "linear-reg1");
+        assert_eq!(marker["version"], "1.1.0");
+        assert_eq!(marker["files"][0], "model.onnx");
+
+        // a new download removes the marker until it succeeds
+        clear_ready_file(&output).unwrap();
+        assert!(!ready_file.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[tokio::test]
     async fn test_make_post_request() {
         let mut server = mockito::Server::new();
@@ -307,9 +538,19 @@ mod tests {
             .create();
 
         let full_path: String = format!("{}/fake", &url);
-        let response = utils::make_post_request(&full_path, &payload).await;
+        let response = utils::make_post_request(&full_path, &payload)
+            .await
+            .unwrap();
 
         assert_eq!(response.status(), 201);
         mock.assert();
+
+        // nothing listens on port 9, so every attempt fails
+        let error = utils::make_post_request("http://127.0.0.1:9/fake", &payload)
+            .await
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Request to http://127.0.0.1:9/fake failed"));
     }
 }
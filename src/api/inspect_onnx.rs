@@ -0,0 +1,616 @@
+use crate::api::download_file::{load_model_metadata, MODEL_METADATA_FILE};
+use crate::api::types;
+use owo_colors::OwoColorize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use tabled::settings::style::Style;
+use tabled::settings::Alignment;
+use tract_onnx::pb;
+use tract_onnx::pb::tensor_proto::DataType;
+use tract_onnx::prelude::Framework;
+
+/// Maximum (IR version, default opset) supported by each onnx release
+const ONNX_RELEASES: [(&str, i64, i64); 19] = [
+    ("1.0", 3, 1),
+    ("1.1", 3, 6),
+    ("1.2", 3, 7),
+    ("1.3", 3, 8),
+    ("1.4", 4, 9),
+    ("1.5", 5, 10),
+    ("1.6", 6, 11),
+    ("1.7", 7, 12),
+    ("1.8", 7, 13),
+    ("1.9", 7, 14),
+    ("1.10", 8, 15),
+    ("1.11", 8, 16),
+    ("1.12", 8, 17),
+    ("1.13", 8, 18),
+    ("1.14", 9, 19),
+    ("1.15", 9, 20),
+    ("1.16", 10, 21),
+    ("1.17", 10, 22),
+    ("1.18", 11, 23),
+];
+
+/// Shape dimension, either fixed or symbolic
+#[derive(Debug, PartialEq)]
+enum Dim {
+    Fixed(i64),
+    Symbolic(String),
+}
+
+/// Graph input or output
+#[derive(Debug)]
+struct GraphValue {
+    name: String,
+    dtype: String,
+    shape: Option<Vec<Dim>>,
+}
+
+/// Result of a metadata check
+struct Check {
+    name: String,
+    passed: bool,
+    detail: String,
+}
+
+/// Gets the onnx name of a tensor data type
+///
+/// * `data_type` - onnx data type enum value
+fn dtype_name(data_type: i32) -> String {
+    match DataType::try_from(data_type) {
+        Ok(dtype) => dtype.as_str_name().to_string(),
+        Err(_) => format!("UNKNOWN({})", data_type),
+    }
+}
+
+/// Gets the byte size of a single element of a tensor data type
+///
+/// * `data_type` - onnx data type enum value
+fn dtype_size(data_type: i32) -> usize {
+    match DataType::try_from(data_type) {
+        Ok(DataType::Uint8 | DataType::Int8 | DataType::Bool) => 1,
+        Ok(DataType::Uint16 | DataType::Int16 | DataType::Float16 | DataType::Bfloat16) => 2,
+        Ok(DataType::Float | DataType::Int32 | DataType::Uint32) => 4,
+        Ok(DataType::Double | DataType::Int64 | DataType::Uint64 | DataType::Complex64) => 8,
+        Ok(DataType::Complex128) => 16,
+        _ => 1,
+    }
+}
+
+/// Maps a data schema feature type to an onnx data type name
+///
+/// * `feature_type` - feature type recorded in the data schema
+fn normalize_feature_type(feature_type: &str) -> Option<&'static str> {
+    match feature_type.to_lowercase().as_str() {
+        "float" | "float32" => Some("FLOAT"),
+        "double" | "float64" => Some("DOUBLE"),
+        "int32" => Some("INT32"),
+        "int64" | "int" => Some("INT64"),
+        "str" | "string" | "object" => Some("STRING"),
+        "bool" => Some("BOOL"),
+        _ => None,
+    }
+}
+
+/// Parses a graph input or output
+///
+/// * `value_info` - onnx value info
+fn parse_value_info(value_info: &pb::ValueInfoProto) -> GraphValue {
+    let tensor_type = value_info
+        .r#type
+        .as_ref()
+        .and_then(|t| t.value.as_ref())
+        .map(|pb::type_proto::Value::TensorType(tensor)| tensor);
+
+    let dtype = match tensor_type {
+        Some(tensor) => dtype_name(tensor.elem_type),
+        None => "UNKNOWN".to_string(),
+    };
+
+    let shape = tensor_type.and_then(|t| t.shape.as_ref()).map(|shape| {
+        shape
+            .dim
+            .iter()
+            .map(|dim| match &dim.value {
+                Some(pb::tensor_shape_proto::dimension::Value::DimValue(v)) => Dim::Fixed(*v),
+                Some(pb::tensor_shape_proto::dimension::Value::DimParam(p)) => {
+                    Dim::Symbolic(p.clone())
+                }
+                None => Dim::Symbolic("?".to_string()),
+            })
+            .collect()
+    });
+
+    GraphValue {
+        name: value_info.name.clone(),
+        dtype,
+        shape,
+    }
+}
+
+/// Renders a shape as a string
+///
+/// * `shape` - shape to render
+fn shape_to_string(shape: &Option<Vec<Dim>>) -> String {
+    match shape {
+        Some(dims) => {
+            let dims: Vec<String> = dims
+                .iter()
+                .map(|dim| match dim {
+                    Dim::Fixed(v) => v.to_string(),
+                    Dim::Symbolic(p) => p.clone(),
+                })
+                .collect();
+            format!("[{}]", dims.join(", "))
+        }
+        None => "unknown".to_string(),
+    }
+}
+
+/// Gets the byte size of an initializer
+///
+/// * `tensor` - onnx initializer
+fn initializer_size(tensor: &pb::TensorProto) -> usize {
+    if !tensor.raw_data.is_empty() {
+        return tensor.raw_data.len();
+    }
+
+    if tensor.data_type == DataType::String as i32 {
+        return tensor.string_data.iter().map(|s| s.len()).sum();
+    }
+
+    // external data records its length alongside the location
+    if let Some(length) = tensor
+        .external_data
+        .iter()
+        .find(|entry| entry.key == "length")
+        .and_then(|entry| entry.value.parse::<usize>().ok())
+    {
+        return length;
+    }
+
+    let elements: i64 = tensor.dims.iter().product();
+    elements.max(0) as usize * dtype_size(tensor.data_type)
+}
+
+/// Gets the max IR version and opset supported by an onnx release
+///
+/// * `onnx_version` - onnx release (e.g. 1.14.0)
+fn get_onnx_release(onnx_version: &str) -> Option<(i64, i64)> {
+    let release: Vec<&str> = onnx_version.split('.').take(2).collect();
+    let release = release.join(".");
+
+    ONNX_RELEASES
+        .iter()
+        .find(|(version, _, _)| *version == release)
+        .map(|(_, ir_version, opset)| (*ir_version, *opset))
+}
+
+/// Gets the default domain opset version of a model
+///
+/// * `model` - onnx model
+fn get_default_opset(model: &pb::ModelProto) -> Option<i64> {
+    model
+        .opset_import
+        .iter()
+        .find(|opset| opset.domain.is_empty() || opset.domain == "ai.onnx")
+        .map(|opset| opset.version)
+}
+
+/// Checks whether a graph shape satisfies a schema shape
+///
+/// Schema shapes may omit the batch dimension. Unknown dimensions match anything.
+///
+/// * `graph_shape` - shape declared in the onnx graph
+/// * `schema_shape` - shape declared in the data schema
+fn shape_matches(graph_shape: &[Dim], schema_shape: &[Option<i64>]) -> bool {
+    let graph_shape = if graph_shape.len() == schema_shape.len() + 1 {
+        &graph_shape[1..]
+    } else if graph_shape.len() == schema_shape.len() {
+        graph_shape
+    } else {
+        return false;
+    };
+
+    graph_shape
+        .iter()
+        .zip(schema_shape.iter())
+        .all(|(graph_dim, schema_dim)| match (graph_dim, schema_dim) {
+            (Dim::Fixed(g), Some(s)) if *s >= 0 => g == s,
+            _ => true,
+        })
+}
+
+/// Cross-checks graph inputs and onnx version against model metadata
+///
+/// * `model` - onnx model
+/// * `inputs` - graph inputs
+/// * `model_metadata` - registered model metadata
+fn check_metadata(
+    model: &pb::ModelProto,
+    inputs: &[GraphValue],
+    model_metadata: &types::ModelMetadata,
+) -> Vec<Check> {
+    let mut checks = Vec::new();
+    let data_schema = &model_metadata.data_schema;
+
+    // dtypes are only comparable against the onnx input schema
+    let (features, check_dtypes): (&HashMap<String, Value>, bool) =
+        match &data_schema.input_data_schema {
+            Some(schema) => (schema, true),
+            None => (&data_schema.model_data_schema.input_features, false),
+        };
+
+    for input in inputs.iter() {
+        let feature = match features.get(&input.name) {
+            Some(feature) => feature,
+            None => {
+                checks.push(Check {
+                    name: format!("input {}", input.name),
+                    passed: false,
+                    detail: "Graph input is not declared in the data schema".to_string(),
+                });
+                continue;
+            }
+        };
+
+        let feature_type = feature.get("feature_type").and_then(|t| t.as_str());
+        if check_dtypes {
+            if let Some(feature_type) = feature_type {
+                let expected = normalize_feature_type(feature_type);
+                let passed = expected == Some(input.dtype.as_str());
+                checks.push(Check {
+                    name: format!("input {} dtype", input.name),
+                    passed,
+                    detail: format!("graph {}, schema {}", input.dtype, feature_type),
+                });
+            }
+        }
+
+        let schema_shape: Option<Vec<Option<i64>>> = feature
+            .get("shape")
+            .and_then(|s| s.as_array())
+            .map(|dims| dims.iter().map(|d| d.as_i64()).collect());
+
+        if let (Some(graph_shape), Some(schema_shape)) = (&input.shape, &schema_shape) {
+            checks.push(Check {
+                name: format!("input {} shape", input.name),
+                passed: shape_matches(graph_shape, schema_shape),
+                detail: format!(
+                    "graph {}, schema {}",
+                    shape_to_string(&input.shape),
+                    Value::from(schema_shape.clone())
+                ),
+            });
+        }
+    }
+
+    let mut missing: Vec<&String> = features
+        .keys()
+        .filter(|name| !inputs.iter().any(|input| &input.name == *name))
+        .collect();
+    missing.sort();
+
+    for name in missing {
+        checks.push(Check {
+            name: format!("input {}", name),
+            passed: false,
+            detail: "Schema feature is not a graph input".to_string(),
+        });
+    }
+
+    match &model_metadata.onnx_version {
+        Some(onnx_version) => match get_onnx_release(onnx_version) {
+            Some((max_ir_version, max_opset)) => {
+                checks.push(Check {
+                    name: "ir version".to_string(),
+                    passed: model.ir_version <= max_ir_version,
+                    detail: format!(
+                        "model IR {}, onnx {} supports up to IR {}",
+                        model.ir_version, onnx_version, max_ir_version
+                    ),
+                });
+
+                if let Some(opset) = get_default_opset(model) {
+                    checks.push(Check {
+                        name: "opset version".to_string(),
+                        passed: opset <= max_opset,
+                        detail: format!(
+                            "model opset {}, onnx {} supports up to opset {}",
+                            opset, onnx_version, max_opset
+                        ),
+                    });
+                }
+            }
+            None => checks.push(Check {
+                name: "onnx version".to_string(),
+                passed: true,
+                detail: format!("Unknown onnx release {}, skipping check", onnx_version),
+            }),
+        },
+        None => checks.push(Check {
+            name: "onnx version".to_string(),
+            passed: false,
+            detail: "No onnx_version registered in model metadata".to_string(),
+        }),
+    }
+
+    checks
+}
+
+/// Builds a table from a header and records
+///
+/// * `header` - table header
+/// * `records` - table rows
+fn build_table(header: Vec<&str>, records: Vec<Vec<String>>) -> String {
+    let mut builder = tabled::builder::Builder::default();
+    builder.set_header(header);
+    for record in records {
+        builder.push_record(record);
+    }
+
+    builder
+        .build()
+        .with(Alignment::center())
+        .with(Style::sharp())
+        .to_string()
+}
+
+/// Renders the summary, io, operator and initializer tables of an onnx model
+///
+/// * `model` - onnx model
+/// * `inputs` - graph inputs
+/// * `outputs` - graph outputs
+fn render_model(model: &pb::ModelProto, inputs: &[GraphValue], outputs: &[GraphValue]) -> String {
+    let graph = model.graph.clone().unwrap_or_default();
+
+    let opsets: Vec<String> = model
+        .opset_import
+        .iter()
+        .map(|opset| {
+            let domain = if opset.domain.is_empty() {
+                "ai.onnx"
+            } else {
+                opset.domain.as_str()
+            };
+            format!("{}:{}", domain, opset.version)
+        })
+        .collect();
+
+    let summary = build_table(
+        vec!["IR Version", "Opsets", "Producer", "Graph"],
+        vec![vec![
+            model.ir_version.to_string(),
+            opsets.join(", "),
+            format!("{} {}", model.producer_name, model.producer_version)
+                .trim()
+                .to_string(),
+            graph.name.clone(),
+        ]],
+    );
+
+    let mut io_records = Vec::new();
+    for (kind, values) in [("input", inputs), ("output", outputs)] {
+        for value in values.iter() {
+            io_records.push(vec![
+                kind.to_string(),
+                value.name.clone(),
+                value.dtype.clone(),
+                shape_to_string(&value.shape),
+            ]);
+        }
+    }
+    let io_table = build_table(vec!["Kind", "Name", "Type", "Shape"], io_records);
+
+    let mut histogram: BTreeMap<String, usize> = BTreeMap::new();
+    for node in graph.node.iter() {
+        let op_type = if node.domain.is_empty() || node.domain == "ai.onnx" {
+            node.op_type.clone()
+        } else {
+            format!("{}:{}", node.domain, node.op_type)
+        };
+        *histogram.entry(op_type).or_insert(0) += 1;
+    }
+    let mut histogram: Vec<(String, usize)> = histogram.into_iter().collect();
+    histogram.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    let op_table = build_table(
+        vec!["Operator", "Count"],
+        histogram
+            .into_iter()
+            .map(|(op, count)| vec![op, count.to_string()])
+            .collect(),
+    );
+
+    let mut total_size = 0;
+    let mut initializer_records = Vec::new();
+    for tensor in graph.initializer.iter() {
+        let size = initializer_size(tensor);
+        total_size += size;
+        initializer_records.push(vec![
+            tensor.name.clone(),
+            dtype_name(tensor.data_type),
+            format!("{:?}", tensor.dims),
+            size.to_string(),
+        ]);
+    }
+    initializer_records.push(vec![
+        "total".to_string(),
+        String::new(),
+        String::new(),
+        total_size.to_string(),
+    ]);
+    let initializer_table = build_table(
+        vec!["Initializer", "Type", "Shape", "Bytes"],
+        initializer_records,
+    );
+
+    [summary, io_table, op_table, initializer_table].join("\n")
+}
+
+/// Inspects an onnx model and cross-checks it against model metadata
+///
+/// Metadata checks run when a `metadata.json` sits alongside the model.
+///
+/// # Arguments
+///
+/// * `path` - Path to the onnx model
+pub fn inspect_onnx(path: &str) -> Result<(), String> {
+    let model = tract_onnx::onnx()
+        .proto_model_for_path(path)
+        .map_err(|e| format!("Failed to parse onnx model {}: {}", path, e))?;
+
+    let graph = model.graph.clone().unwrap_or_default();
+
+    // initializers may also be listed as graph inputs in older IR versions
+    let inputs: Vec<GraphValue> = graph
+        .input
+        .iter()
+        .filter(|input| !graph.initializer.iter().any(|t| t.name == input.name))
+        .map(parse_value_info)
+        .collect();
+    let outputs: Vec<GraphValue> = graph.output.iter().map(parse_value_info).collect();
+
+    println!("{}", render_model(&model, &inputs, &outputs));
+
+    let write_dir = match Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_str().unwrap_or("."),
+        _ => ".",
+    };
+
+    if !Path::new(write_dir).join(MODEL_METADATA_FILE).exists() {
+        return Ok(());
+    }
+
+    let model_metadata = load_model_metadata(write_dir)?;
+    let checks = check_metadata(&model, &inputs, &model_metadata);
+    let failed = checks.iter().filter(|check| !check.passed).count();
+
+    let check_table = build_table(
+        vec!["Check", "Status", "Detail"],
+        checks
+            .into_iter()
+            .map(|check| {
+                let status = if check.passed {
+                    "pass".green().to_string()
+                } else {
+                    "fail".red().to_string()
+                };
+                vec![check.name, status, check.detail]
+            })
+            .collect(),
+    );
+    println!("{}", check_table);
+
+    if failed > 0 {
+        return Err(format!(
+            "{} mismatch(es) between onnx model and {}",
+            failed, MODEL_METADATA_FILE
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn load_model() -> (pb::ModelProto, Vec<GraphValue>) {
+        let model = tract_onnx::onnx()
+            .proto_model_for_path("./src/api/test_utils/model.onnx")
+            .unwrap();
+        let inputs = model
+            .graph
+            .as_ref()
+            .unwrap()
+            .input
+            .iter()
+            .map(parse_value_info)
+            .collect();
+        (model, inputs)
+    }
+
+    /// Cells of the first table row starting with a given cell
+    fn row(rendered: &str, first: &str) -> Vec<String> {
+        rendered
+            .lines()
+            .map(|line| {
+                line.trim_matches('│')
+                    .split('│')
+                    .map(|cell| cell.trim().to_string())
+                    .collect::<Vec<String>>()
+            })
+            .find(|cells| cells[0] == first)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_render_model() {
+        let (model, inputs) = load_model();
+        assert_eq!(inputs.len(), 10);
+        assert_eq!(inputs[0].dtype, "FLOAT");
+        assert_eq!(shape_to_string(&inputs[0].shape), "[N, 1]");
+
+        let graph = model.graph.as_ref().unwrap();
+        let outputs: Vec<GraphValue> = graph.output.iter().map(parse_value_info).collect();
+        let rendered = render_model(&model, &inputs, &outputs);
+
+        assert_eq!(
+            row(&rendered, "8"),
+            vec!["8", "ai.onnx:13", "opsml-cli", "linear-reg1"]
+        );
+        assert_eq!(
+            row(&rendered, "output"),
+            vec!["output", "variable", "FLOAT", "[N, 1]"]
+        );
+        assert_eq!(row(&rendered, "MatMul"), vec!["MatMul", "1"]);
+        // 10 float coefficients and 1 float intercept
+        assert_eq!(
+            row(&rendered, "coef"),
+            vec!["coef", "FLOAT", "[10, 1]", "40"]
+        );
+        assert_eq!(row(&rendered, "total"), vec!["total", "", "", "44"]);
+    }
+
+    #[test]
+    fn test_check_metadata() {
+        let (model, inputs) = load_model();
+        let path = "./src/api/test_utils/metadata_onnx.json";
+        let data = fs::read_to_string(path).expect("Unable to read file");
+        let mut model_metadata: types::ModelMetadata = serde_json::from_str(&data).unwrap();
+
+        // fixture declares onnx 1.0.0, which predates IR 8 / opset 13
+        let checks = check_metadata(&model, &inputs, &model_metadata);
+        let failed: Vec<&String> = checks
+            .iter()
+            .filter(|check| !check.passed)
+            .map(|check| &check.name)
+            .collect();
+        assert_eq!(failed, vec!["ir version", "opset version"]);
+
+        model_metadata.onnx_version = Some("1.14.0".to_string());
+        let checks = check_metadata(&model, &inputs, &model_metadata);
+        assert!(checks.iter().all(|check| check.passed));
+
+        // dropping a graph input is flagged
+        let checks = check_metadata(&model, &inputs[1..], &model_metadata);
+        let failed: Vec<&String> = checks
+            .iter()
+            .filter(|check| !check.passed)
+            .map(|check| &check.name)
+            .collect();
+        assert_eq!(failed, vec!["input col_0"]);
+    }
+
+    #[test]
+    fn test_shape_matches() {
+        let graph_shape = vec![Dim::Symbolic("N".to_string()), Dim::Fixed(1)];
+        assert!(shape_matches(&graph_shape, &[None, Some(1)]));
+        assert!(shape_matches(&graph_shape, &[Some(1)]));
+        assert!(!shape_matches(&graph_shape, &[Some(2)]));
+        assert!(!shape_matches(&graph_shape, &[Some(1), Some(1), Some(1)]));
+    }
+}
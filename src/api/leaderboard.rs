@@ -0,0 +1,278 @@
+use crate::api::compare_spec::{parse_metric_arg, Direction};
+use crate::api::list_cards::{get_cards, get_registry};
+use crate::api::metrics::{fetch_model_metrics, metric_series};
+use crate::api::output::{render_csv, render_table, OutputFormat};
+use crate::api::types;
+use crate::api::utils;
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+
+/// Number of metric requests in flight at once
+const METRIC_REQUEST_CONCURRENCY: usize = 8;
+
+/// Model version ranked by a metric
+///
+/// Versions whose metrics could not be fetched are listed unranked with the error.
+#[derive(Debug, PartialEq, Serialize)]
+struct LeaderboardEntry {
+    rank: Option<usize>,
+    name: String,
+    version: String,
+    date: String,
+    uid: String,
+    metric: String,
+    value: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl LeaderboardEntry {
+    fn to_record(&self) -> Vec<String> {
+        let value = match (&self.value, &self.error) {
+            (Some(value), _) => value.to_string(),
+            (None, Some(error)) => format!("error: {}", error),
+            (None, None) => "missing".to_string(),
+        };
+
+        vec![
+            self.rank.map_or("-".to_string(), |rank| rank.to_string()),
+            self.name.clone(),
+            self.version.clone(),
+            self.date.clone(),
+            self.uid.clone(),
+            value,
+        ]
+    }
+}
+
+/// Ranks cards from best to worst metric value
+///
+/// Cards that did not log the metric are left out, cards whose metrics could not
+/// be fetched follow the ranked entries. `top` limits the combined list.
+///
+/// * `cards` - cards with their metric value or fetch error
+/// * `metric` - metric name
+/// * `direction` - direction in which the metric improves
+/// * `top` - maximum number of entries to return
+fn rank_cards(
+    cards: Vec<(types::Card, Result<Option<f64>, String>)>,
+    metric: &str,
+    direction: Direction,
+    top: Option<usize>,
+) -> Vec<LeaderboardEntry> {
+    let mut ranked: Vec<(types::Card, f64)> = Vec::new();
+    let mut errored: Vec<(types::Card, String)> = Vec::new();
+
+    for (card, value) in cards {
+        match value {
+            Ok(Some(value)) => ranked.push((card, value)),
+            Ok(None) => {}
+            Err(e) => errored.push((card, e)),
+        }
+    }
+
+    ranked.sort_by(|a, b| match direction {
+        Direction::Min => a.1.total_cmp(&b.1),
+        Direction::Max => b.1.total_cmp(&a.1),
+    });
+
+    ranked
+        .into_iter()
+        .enumerate()
+        .map(|(i, (card, value))| LeaderboardEntry {
+            rank: Some(i + 1),
+            name: card.name,
+            version: card.version,
+            date: card.date,
+            uid: card.uid,
+            metric: metric.to_string(),
+            value: Some(value),
+            error: None,
+        })
+        .chain(errored.into_iter().map(|(card, error)| LeaderboardEntry {
+            rank: None,
+            name: card.name,
+            version: card.version,
+            date: card.date,
+            uid: card.uid,
+            metric: metric.to_string(),
+            value: None,
+            error: Some(error),
+        }))
+        .take(top.unwrap_or(usize::MAX))
+        .collect()
+}
+
+/// Ranks model versions by a metric
+///
+/// Uses the last logged step of metrics logged at multiple steps. Metrics have no
+/// common direction (auc improves upwards, mae downwards), so it must be given.
+///
+/// # Arguments
+///
+/// * `name` - Model name
+/// * `team` - Team name
+/// * `metric` - Metric name with a direction (e.g. auc:max)
+/// * `top` - Maximum number of versions to show
+/// * `format` - Output format
+#[tokio::main]
+pub async fn leaderboard(
+    name: Option<&str>,
+    team: Option<&str>,
+    metric: &str,
+    top: Option<usize>,
+    format: OutputFormat,
+) -> Result<(), utils::RequestError> {
+    let (metric, direction) = parse_metric_arg(metric)?;
+    let direction = direction.ok_or_else(|| {
+        format!(
+            "Metric {} needs a direction to rank by, e.g. {}:max or {}:min",
+            metric, metric, metric
+        )
+    })?;
+
+    let list_table_request = types::ListTableRequest {
+        table_name: get_registry("model"),
+        name: name.map(|s| s.to_string()),
+        team: team.map(|s| s.to_string()),
+        version: None,
+        uid: None,
+        limit: None,
+        tags: None,
+        max_date: None,
+    };
+    let cards = get_cards(&list_table_request).await?.cards;
+
+    if cards.is_empty() {
+        return Err("No model cards found".into());
+    }
+
+    let values: Vec<Result<Option<f64>, utils::RequestError>> = stream::iter(cards.iter())
+        .map(|card| {
+            let metric = &metric;
+            async move {
+                let request = types::CardRequest {
+                    name: None,
+                    version: None,
+                    uid: Some(card.uid.clone()),
+                };
+                let metrics = fetch_model_metrics(&request).await?;
+                Ok(metric_series(&metrics, metric).last().copied())
+            }
+        })
+        .buffered(METRIC_REQUEST_CONCURRENCY)
+        .collect()
+        .await;
+
+    // the metric requests were printed instead of sent
+    if values
+        .iter()
+        .any(|value| matches!(value, Err(utils::RequestError::DryRun)))
+    {
+        return Err(utils::RequestError::DryRun);
+    }
+
+    let missing = values
+        .iter()
+        .filter(|value| matches!(value, Ok(None)))
+        .count();
+    let failed = values.iter().filter(|value| value.is_err()).count();
+    let total = cards.len();
+    let entries = rank_cards(
+        cards
+            .into_iter()
+            .zip(
+                values
+                    .into_iter()
+                    .map(|value| value.map_err(|e| e.to_string())),
+            )
+            .collect(),
+        &metric,
+        direction,
+        top,
+    );
+
+    let header = ["Rank", "Name", "Version", "Date", "Uid", metric.as_str()];
+    let records: Vec<Vec<String>> = entries.iter().map(|e| e.to_record()).collect();
+
+    match format {
+        OutputFormat::Table => println!("{}", render_table(&header, &records)),
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&entries).map_err(|e| e.to_string())?
+        ),
+        OutputFormat::Csv => print!("{}", render_csv(&header, &records)?),
+    }
+
+    if missing > 0 {
+        eprintln!("{} of {} versions did not log {}", missing, total, metric);
+    }
+    if failed > 0 {
+        eprintln!(
+            "Failed to fetch metrics for {} of {} versions",
+            failed, total
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn card(version: &str) -> types::Card {
+        types::Card {
+            name: "model".to_string(),
+            team: "team".to_string(),
+            date: "2023-08-01".to_string(),
+            user_email: "fake_email".to_string(),
+            version: version.to_string(),
+            uid: format!("uid-{}", version),
+            tags: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_rank_cards() {
+        let cards = vec![
+            (card("1.0.0"), Ok(Some(0.7))),
+            (card("1.1.0"), Ok(None)),
+            (card("1.2.0"), Ok(Some(0.9))),
+            (card("1.3.0"), Ok(Some(0.8))),
+            (card("1.4.0"), Err("Failed to get metrics".to_string())),
+        ];
+
+        let entries = rank_cards(cards.clone(), "auc", Direction::Max, None);
+        let versions: Vec<&str> = entries.iter().map(|e| e.version.as_str()).collect();
+        assert_eq!(versions, vec!["1.2.0", "1.3.0", "1.0.0", "1.4.0"]);
+        assert_eq!(entries[0].rank, Some(1));
+        assert_eq!(
+            entries[0].to_record(),
+            vec!["1", "model", "1.2.0", "2023-08-01", "uid-1.2.0", "0.9"]
+        );
+
+        // versions whose metrics failed to load are listed unranked
+        assert_eq!(
+            entries[3].to_record(),
+            vec![
+                "-",
+                "model",
+                "1.4.0",
+                "2023-08-01",
+                "uid-1.4.0",
+                "error: Failed to get metrics"
+            ]
+        );
+
+        // top limits the ranked and unranked entries together
+        let entries = rank_cards(cards.clone(), "auc", Direction::Min, Some(2));
+        let versions: Vec<&str> = entries.iter().map(|e| e.version.as_str()).collect();
+        assert_eq!(versions, vec!["1.0.0", "1.3.0"]);
+
+        let entries = rank_cards(cards, "auc", Direction::Min, Some(4));
+        let versions: Vec<&str> = entries.iter().map(|e| e.version.as_str()).collect();
+        assert_eq!(versions, vec!["1.0.0", "1.3.0", "1.2.0", "1.4.0"]);
+    }
+}
@@ -1,6 +1,5 @@
 use crate::api::types;
 use crate::api::utils;
-use reqwest;
 use serde_json;
 use std::collections::HashMap;
 use tabled::settings::style::Style;
@@ -25,7 +24,7 @@ impl OpsmlRegistries {
     }
 }
 
-fn get_registry(registry: &str) -> String {
+pub fn get_registry(registry: &str) -> String {
     // Determines correct  registry to use
 
     if registry == "data" {
@@ -34,7 +33,7 @@ fn get_registry(registry: &str) -> String {
         return OpsmlRegistries::Model.as_str();
     } else if registry == "run" {
         return OpsmlRegistries::Run.as_str();
-    } else if registry == "model" {
+    } else if registry == "pipeline" {
         return OpsmlRegistries::Pipeline.as_str();
     } else if registry == "audit" {
         return OpsmlRegistries::Audit.as_str();
@@ -67,7 +66,27 @@ fn parse_list_response(response: &str) -> String {
         .with(Style::sharp())
         .to_string();
 
-    return list_table;
+    list_table
+}
+
+/// Gets cards from a registry
+///
+/// # Arguments
+///
+/// * `list_table_request` - Registry list request
+pub async fn get_cards(
+    list_table_request: &types::ListTableRequest,
+) -> Result<types::ListCardResponse, String> {
+    let response =
+        utils::make_lookup_request(&utils::OpsmlPaths::ListCard.as_str()?, list_table_request)
+            .await?;
+
+    if !response.status().is_success() {
+        return Err(format!("Failed to list cards: {}", response.status()));
+    }
+
+    let text = response.text().await.map_err(|e| e.to_string())?;
+    serde_json::from_str(&text).map_err(|e| format!("Failed to parse card list: {}", e))
 }
 
 /// List cards
@@ -83,6 +102,7 @@ fn parse_list_response(response: &str) -> String {
 /// * `url` - OpsML url
 /// * `tag_name` - Tag name
 /// * `tag_value` - Tag value
+#[allow(clippy::too_many_arguments)]
 #[tokio::main]
 pub async fn list_cards(
     registry: &str,
@@ -94,41 +114,41 @@ pub async fn list_cards(
     tag_name: Option<Vec<String>>,
     tag_value: Option<Vec<String>>,
     max_date: Option<&str>,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), utils::RequestError> {
     // set full path and table name
 
     let mut tags: HashMap<String, String> = HashMap::new();
-    let table_name: String = get_registry(&registry);
+    let table_name: String = get_registry(registry);
 
-    if tag_name.is_some() && tag_value.is_some() {
+    if let (Some(tag_name), Some(tag_value)) = (tag_name, tag_value) {
         tags = tag_name
-            .unwrap()
             .iter()
-            .zip(tag_value.unwrap().iter())
+            .zip(tag_value.iter())
             .map(|(k, v)| (k.to_string(), v.to_string()))
             .collect();
     }
 
     let list_table_request = types::ListTableRequest {
-        table_name: table_name,
+        table_name,
         name: name.map(|s| s.to_string()),
         team: team.map(|s| s.to_string()),
         version: version.map(|s| s.to_string()),
-        limit: limit,
+        limit,
         uid: uid.map(|s| s.to_string()),
         tags: Some(tags),
         max_date: max_date.map(|s| s.to_string()),
     };
 
     let response =
-        utils::make_post_request(&utils::OpsmlPaths::ListCard.as_str(), &list_table_request).await;
+        utils::make_post_request(&utils::OpsmlPaths::ListCard.as_str()?, &list_table_request)
+            .await?;
 
     if response.status().is_success() {
         let card_table = parse_list_response(&response.text().await.unwrap());
         println!("{}", card_table);
     } else {
-        println!("Failed to list cards");
-        response.error_for_status_ref()?;
+        eprintln!("Failed to list cards");
+        response.error_for_status_ref().map_err(|e| e.to_string())?;
     }
 
     Ok(())
@@ -140,7 +160,7 @@ mod tests {
 
     #[test]
     fn test_get_registry() {
-        let v = vec!["data", "model", "run", "audit"];
+        let v = vec!["data", "model", "run", "pipeline", "audit"];
 
         for name in &v {
             let regsitry: String = get_registry(name);
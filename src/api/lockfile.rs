@@ -0,0 +1,550 @@
+use crate::api::download_file::{download_model_file, get_model_metadata, MODEL_METADATA_FILE};
+use crate::api::resolve::{resolve_cards, resolve_version_req, ModelSelector};
+use crate::api::types;
+use crate::api::utils;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::path::Path;
+use std::{fmt, fs, io};
+
+/// Header written at the top of lockfiles
+const LOCKFILE_HEADER: &str = "# Generated by opsml-cli lock. Do not edit by hand.\n\n";
+
+/// Model file that can be pinned in a manifest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ArtifactKind {
+    /// Onnx model
+    Onnx,
+    /// Trained model
+    Model,
+}
+
+impl fmt::Display for ArtifactKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArtifactKind::Onnx => write!(f, "onnx"),
+            ArtifactKind::Model => write!(f, "model"),
+        }
+    }
+}
+
+fn default_artifacts() -> Vec<ArtifactKind> {
+    vec![ArtifactKind::Onnx]
+}
+
+/// Model entry as written in opsml.toml
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestEntry {
+    name: Option<String>,
+    /// Semver requirement, e.g. ^1.2 or =1.0.0
+    version: Option<String>,
+    uid: Option<String>,
+    #[serde(default = "default_artifacts")]
+    artifacts: Vec<ArtifactKind>,
+    target: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    #[serde(default, rename = "model")]
+    models: Vec<ManifestEntry>,
+}
+
+/// How a manifest entry refers to a model
+#[derive(Debug, PartialEq)]
+enum ModelSource {
+    Uid(String),
+    Version {
+        name: String,
+        requirement: semver::VersionReq,
+    },
+}
+
+impl fmt::Display for ModelSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ModelSource::Uid(uid) => write!(f, "{}", uid),
+            ModelSource::Version { name, requirement } => write!(f, "{} {}", name, requirement),
+        }
+    }
+}
+
+/// Validated manifest entry
+#[derive(Debug, PartialEq)]
+struct ModelRequirement {
+    source: ModelSource,
+    artifacts: Vec<ArtifactKind>,
+    target: String,
+}
+
+/// Artifact pinned to a checksum
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct LockedArtifact {
+    kind: ArtifactKind,
+    /// File name within the model target directory
+    file: String,
+    sha256: String,
+}
+
+/// Model pinned to a concrete version
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct LockedModel {
+    name: String,
+    version: String,
+    uid: String,
+    target: String,
+    artifacts: Vec<LockedArtifact>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Lockfile {
+    #[serde(default, rename = "model")]
+    models: Vec<LockedModel>,
+}
+
+/// Validates manifest entries
+///
+/// * `data` - opsml.toml contents
+fn parse_manifest(data: &str) -> Result<Vec<ModelRequirement>, String> {
+    let manifest: Manifest = toml::from_str(data).map_err(|e| e.to_string())?;
+    let mut targets = HashSet::new();
+    let mut requirements = Vec::new();
+
+    for (i, entry) in manifest.models.into_iter().enumerate() {
+        let invalid = |message: &str| format!("model {}: {}", i + 1, message);
+
+        let (source, label) = match (entry.name, entry.version, entry.uid) {
+            (None, None, Some(uid)) => (ModelSource::Uid(uid.clone()), uid),
+            (Some(name), version, None) => {
+                let version = version.unwrap_or_else(|| "*".to_string());
+                let requirement = semver::VersionReq::parse(&version)
+                    .map_err(|e| invalid(&format!("invalid version {}: {}", version, e)))?;
+                (
+                    ModelSource::Version {
+                        name: name.clone(),
+                        requirement,
+                    },
+                    name,
+                )
+            }
+            (_, _, Some(_)) => return Err(invalid("uid cannot be combined with name or version")),
+            (None, _, None) => return Err(invalid("expected a name or uid")),
+        };
+
+        if entry.artifacts.is_empty() {
+            return Err(invalid("expected at least one artifact"));
+        }
+
+        let mut artifacts = Vec::new();
+        for kind in entry.artifacts {
+            if !artifacts.contains(&kind) {
+                artifacts.push(kind);
+            }
+        }
+
+        let target = entry.target.unwrap_or_else(|| format!(".models/{}", label));
+        if !targets.insert(target.clone()) {
+            return Err(invalid(&format!(
+                "target {} is used by another model",
+                target
+            )));
+        }
+
+        requirements.push(ModelRequirement {
+            source,
+            artifacts,
+            target,
+        });
+    }
+
+    if requirements.is_empty() {
+        return Err("No models listed".to_string());
+    }
+
+    Ok(requirements)
+}
+
+/// Loads a manifest
+///
+/// * `path` - path to opsml.toml
+fn load_manifest(path: &str) -> Result<Vec<ModelRequirement>, String> {
+    let data =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read manifest {}: {}", path, e))?;
+    parse_manifest(&data).map_err(|e| format!("Invalid manifest {}: {}", path, e))
+}
+
+/// Loads a lockfile
+///
+/// * `path` - path to opsml.lock
+fn load_lockfile(path: &str) -> Result<Lockfile, String> {
+    let data =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read lockfile {}: {}", path, e))?;
+    toml::from_str(&data).map_err(|e| format!("Failed to parse lockfile {}: {}", path, e))
+}
+
+/// Writes a lockfile
+///
+/// * `path` - path to opsml.lock
+/// * `lockfile` - locked models
+fn write_lockfile(path: &str, lockfile: &Lockfile) -> Result<(), String> {
+    let data = toml::to_string(lockfile).map_err(|e| e.to_string())?;
+    fs::write(path, format!("{}{}", LOCKFILE_HEADER, data))
+        .map_err(|e| format!("Failed to write lockfile {}: {}", path, e))
+}
+
+/// Computes the hex encoded sha256 of a file
+///
+/// * `path` - file to hash
+fn sha256_file(path: &Path) -> Result<String, String> {
+    let mut file =
+        fs::File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Returns the registry uri and local file name of an artifact
+///
+/// * `metadata` - model metadata
+/// * `kind` - artifact kind
+pub fn artifact_uri(
+    metadata: &types::ModelMetadata,
+    kind: ArtifactKind,
+) -> Result<(String, String), String> {
+    let uri = match kind {
+        ArtifactKind::Onnx => metadata.onnx_uri.clone().ok_or_else(|| {
+            format!(
+                "{} {} has no onnx model",
+                metadata.model_name, metadata.model_version
+            )
+        })?,
+        ArtifactKind::Model => metadata.model_uri.clone(),
+    };
+
+    let file = Path::new(&uri)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| format!("Invalid {} uri {}", kind, uri))?
+        .to_string();
+
+    Ok((uri, file))
+}
+
+/// Returns the locked artifacts that are missing or fail their checksum
+///
+/// * `model` - locked model
+fn stale_artifacts(model: &LockedModel) -> Vec<&LockedArtifact> {
+    model
+        .artifacts
+        .iter()
+        .filter(|artifact| {
+            let path = Path::new(&model.target).join(&artifact.file);
+            sha256_file(&path).ok().as_deref() != Some(artifact.sha256.as_str())
+        })
+        .collect()
+}
+
+/// Downloads an artifact and returns its checksum
+///
+/// * `uri` - registry uri
+/// * `path` - local file path
+async fn download_artifact(uri: &str, path: &Path) -> Result<String, utils::RequestError> {
+    download_model_file(
+        &utils::OpsmlPaths::Download.as_str()?,
+        uri,
+        &path.to_string_lossy(),
+    )
+    .await?;
+
+    Ok(sha256_file(path)?)
+}
+
+/// Resolves a manifest entry to a card and downloads its artifacts
+///
+/// * `requirement` - manifest entry
+async fn lock_model(requirement: &ModelRequirement) -> Result<LockedModel, utils::RequestError> {
+    let card = match &requirement.source {
+        ModelSource::Uid(uid) => resolve_cards(&ModelSelector::Uid(uid.clone()))
+            .await?
+            .remove(0),
+        ModelSource::Version { name, requirement } => {
+            resolve_version_req(name, requirement).await?
+        }
+    };
+
+    let metadata =
+        get_model_metadata(None, None, Some(card.uid.clone()), &requirement.target).await?;
+
+    let mut artifacts = Vec::new();
+    for kind in requirement.artifacts.iter() {
+        let (uri, file) = artifact_uri(&metadata, *kind)?;
+        let path = Path::new(&requirement.target).join(&file);
+
+        artifacts.push(LockedArtifact {
+            kind: *kind,
+            file,
+            sha256: download_artifact(&uri, &path).await?,
+        });
+    }
+
+    Ok(LockedModel {
+        name: card.name,
+        version: card.version,
+        uid: card.uid,
+        target: requirement.target.clone(),
+        artifacts,
+    })
+}
+
+/// Downloads the locked artifacts of a model that are missing or changed
+///
+/// Returns false when the model was already up to date.
+///
+/// * `model` - locked model
+async fn sync_model(model: &LockedModel) -> Result<bool, utils::RequestError> {
+    let stale = stale_artifacts(model);
+    let has_metadata = Path::new(&model.target).join(MODEL_METADATA_FILE).exists();
+
+    if stale.is_empty() && has_metadata {
+        return Ok(false);
+    }
+
+    let metadata = get_model_metadata(None, None, Some(model.uid.clone()), &model.target).await?;
+    if metadata.model_version != model.version {
+        return Err(format!(
+            "Registry returned version {} for {}, lockfile has {}",
+            metadata.model_version, model.uid, model.version
+        )
+        .into());
+    }
+
+    for artifact in stale {
+        let (uri, _) = artifact_uri(&metadata, artifact.kind)?;
+        let path = Path::new(&model.target).join(&artifact.file);
+        let sha256 = download_artifact(&uri, &path).await?;
+
+        if sha256 != artifact.sha256 {
+            let _ = fs::remove_file(&path);
+            return Err(format!(
+                "Checksum mismatch for {}: expected {}, got {}",
+                path.display(),
+                artifact.sha256,
+                sha256
+            )
+            .into());
+        }
+    }
+
+    Ok(true)
+}
+
+/// Resolves the models in a manifest and pins them in a lockfile
+///
+/// Artifacts are downloaded to each model's target directory to record their checksums.
+///
+/// # Arguments
+///
+/// * `manifest` - Path to opsml.toml
+/// * `lockfile` - Path to opsml.lock
+#[tokio::main]
+pub async fn lock(manifest: &str, lockfile: &str) -> Result<(), utils::RequestError> {
+    let requirements = load_manifest(manifest)?;
+    let mut locked = Lockfile::default();
+
+    for requirement in requirements.iter() {
+        let model = lock_model(requirement)
+            .await
+            .map_err(|e| e.context(&format!("Failed to lock {}", requirement.source)))?;
+
+        println!(
+            "Locked {} {} ({}) to {}",
+            model.name, model.version, model.uid, model.target
+        );
+        locked.models.push(model);
+    }
+
+    write_lockfile(lockfile, &locked)?;
+    println!("Wrote {} models to {}", locked.models.len(), lockfile);
+
+    Ok(())
+}
+
+/// Downloads the models pinned in a lockfile
+///
+/// Models whose artifacts are present and match their checksums are skipped.
+///
+/// # Arguments
+///
+/// * `lockfile` - Path to opsml.lock
+#[tokio::main]
+pub async fn sync(lockfile: &str) -> Result<(), utils::RequestError> {
+    let locked = load_lockfile(lockfile)?;
+    let mut synced = 0;
+
+    for model in locked.models.iter() {
+        let updated = sync_model(model)
+            .await
+            .map_err(|e| e.context(&format!("Failed to sync {} {}", model.name, model.version)))?;
+
+        if updated {
+            synced += 1;
+            println!(
+                "Synced {} {} to {}",
+                model.name, model.version, model.target
+            );
+        } else {
+            println!("{} {} is up to date", model.name, model.version);
+        }
+    }
+
+    println!(
+        "Synced {} models, {} up to date",
+        synced,
+        locked.models.len() - synced
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODEL_ONNX_SHA256: &str =
+        "66124cbf1ac2fd842d4a2c271792b0965dc9eadacdbab5f8b98b404efc3baf1e";
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = concat!(
+            "[[model]]\n",
+            "name = \"linear-reg\"\n",
+            "version = \"^1.2\"\n",
+            "artifacts = [\"onnx\", \"model\", \"onnx\"]\n",
+            "\n",
+            "[[model]]\n",
+            "uid = \"abc123\"\n",
+            "target = \"models/pinned\"\n",
+        );
+
+        let requirements = parse_manifest(manifest).unwrap();
+        assert_eq!(
+            requirements[0],
+            ModelRequirement {
+                source: ModelSource::Version {
+                    name: "linear-reg".to_string(),
+                    requirement: semver::VersionReq::parse("^1.2").unwrap(),
+                },
+                artifacts: vec![ArtifactKind::Onnx, ArtifactKind::Model],
+                target: ".models/linear-reg".to_string(),
+            }
+        );
+        assert_eq!(
+            requirements[1],
+            ModelRequirement {
+                source: ModelSource::Uid("abc123".to_string()),
+                artifacts: vec![ArtifactKind::Onnx],
+                target: "models/pinned".to_string(),
+            }
+        );
+
+        for (manifest, error) in [
+            (
+                "[[model]]\nuid = \"a\"\nname = \"m\"\n",
+                "uid cannot be combined",
+            ),
+            ("[[model]]\nversion = \"1\"\n", "expected a name or uid"),
+            (
+                "[[model]]\nname = \"m\"\nversion = \"one\"\n",
+                "invalid version one",
+            ),
+            (
+                "[[model]]\nname = \"m\"\nartifacts = []\n",
+                "at least one artifact",
+            ),
+            (
+                "[[model]]\nuid = \"m\"\n[[model]]\nname = \"m\"\n",
+                "target .models/m",
+            ),
+            (
+                "[[model]]\nname = \"m\"\nartifacts = [\"pkl\"]\n",
+                "unknown variant",
+            ),
+            ("", "No models listed"),
+        ] {
+            let result = parse_manifest(manifest);
+            assert!(result.unwrap_err().contains(error), "{}", manifest);
+        }
+    }
+
+    #[test]
+    fn test_lockfile() {
+        let dir = std::env::temp_dir().join("opsml_lockfile_test");
+        fs::create_dir_all(&dir).unwrap();
+        fs::copy("./src/api/test_utils/model.onnx", dir.join("model.onnx")).unwrap();
+
+        let model = LockedModel {
+            name: "linear-reg".to_string(),
+            version: "1.2.0".to_string(),
+            uid: "abc123".to_string(),
+            target: dir.to_string_lossy().to_string(),
+            artifacts: vec![
+                LockedArtifact {
+                    kind: ArtifactKind::Onnx,
+                    file: "model.onnx".to_string(),
+                    sha256: MODEL_ONNX_SHA256.to_string(),
+                },
+                LockedArtifact {
+                    kind: ArtifactKind::Model,
+                    file: "model.pkl".to_string(),
+                    sha256: MODEL_ONNX_SHA256.to_string(),
+                },
+            ],
+        };
+
+        assert_eq!(
+            sha256_file(&dir.join("model.onnx")).unwrap(),
+            MODEL_ONNX_SHA256
+        );
+
+        // the missing model.pkl is the only stale artifact
+        let stale = stale_artifacts(&model);
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale[0].file, "model.pkl");
+
+        // a modified file no longer matches its checksum
+        fs::write(dir.join("model.onnx"), b"changed").unwrap();
+        assert_eq!(stale_artifacts(&model).len(), 2);
+
+        let path = dir.join("opsml.lock");
+        let lockfile = Lockfile {
+            models: vec![model],
+        };
+        write_lockfile(path.to_str().unwrap(), &lockfile).unwrap();
+        let data = fs::read_to_string(&path).unwrap();
+        assert!(data.starts_with(LOCKFILE_HEADER));
+        assert!(data.contains("[[model.artifacts]]\nkind = \"onnx\""));
+        assert_eq!(load_lockfile(path.to_str().unwrap()).unwrap(), lockfile);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_artifact_uri() {
+        let data = fs::read_to_string("./src/api/test_utils/metadata_non_onnx.json").unwrap();
+        let metadata: types::ModelMetadata = serde_json::from_str(&data).unwrap();
+
+        let (uri, file) = artifact_uri(&metadata, ArtifactKind::Model).unwrap();
+        assert_eq!(uri, metadata.model_uri);
+        assert_eq!(file, "model.pkl");
+        assert!(artifact_uri(&metadata, ArtifactKind::Onnx)
+            .unwrap_err()
+            .contains("has no onnx model"));
+    }
+}
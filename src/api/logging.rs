@@ -0,0 +1,208 @@
+use clap::ValueEnum;
+use serde_json::{Map, Value};
+use std::fmt;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Headers whose values are never written to logs
+const REDACTED_HEADERS: [&str; 4] = [
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "x-api-key",
+];
+
+/// Format log lines are written in
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum LogFormat {
+    /// key=value text
+    #[default]
+    Text,
+    /// One json object per line
+    Json,
+}
+
+/// Log severity, from least to most verbose
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+        };
+        f.pad(name)
+    }
+}
+
+struct LogConfig {
+    level: Level,
+    format: LogFormat,
+}
+
+static LOG_CONFIG: OnceLock<LogConfig> = OnceLock::new();
+
+/// Sets up logging to stderr
+///
+/// Warnings are logged by default, `-v` adds http calls and `-vv` adds request details.
+///
+/// # Arguments
+///
+/// * `verbosity` - Number of -v flags
+/// * `format` - Log line format
+pub fn init(verbosity: u8, format: LogFormat) {
+    let level = match verbosity {
+        0 => Level::Warn,
+        1 => Level::Info,
+        _ => Level::Debug,
+    };
+
+    let _ = LOG_CONFIG.set(LogConfig { level, format });
+}
+
+/// Whether events at a level are logged
+///
+/// * `level` - event level
+pub fn enabled(level: Level) -> bool {
+    let max = LOG_CONFIG.get().map_or(Level::Warn, |config| config.level);
+    level <= max
+}
+
+/// Replaces the value of credential headers
+///
+/// * `name` - header name
+/// * `value` - header value
+pub fn redact_header(name: &str, value: &str) -> String {
+    let name = name.to_lowercase();
+
+    if REDACTED_HEADERS.contains(&name.as_str())
+        || name.contains("token")
+        || name.contains("secret")
+    {
+        "[redacted]".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Quotes text values containing spaces or quotes
+///
+/// * `value` - field value
+fn text_value(value: &Value) -> String {
+    match value {
+        Value::String(text) if text.is_empty() || text.contains([' ', '"', '=']) => {
+            format!("{:?}", text)
+        }
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Formats a log line
+///
+/// * `format` - log line format
+/// * `timestamp` - seconds since the unix epoch
+/// * `level` - event level
+/// * `message` - event message
+/// * `fields` - event fields
+fn format_event(
+    format: LogFormat,
+    timestamp: f64,
+    level: Level,
+    message: &str,
+    fields: &[(&str, Value)],
+) -> String {
+    match format {
+        LogFormat::Text => {
+            let mut line = format!("{:.3} {:<5} {}", timestamp, level, message);
+            for (key, value) in fields.iter() {
+                line.push_str(&format!(" {}={}", key, text_value(value)));
+            }
+            line
+        }
+        LogFormat::Json => {
+            let mut object = Map::new();
+            object.insert("timestamp".to_string(), timestamp.into());
+            object.insert("level".to_string(), level.to_string().into());
+            object.insert("message".to_string(), message.into());
+            for (key, value) in fields.iter() {
+                object.insert(key.to_string(), value.clone());
+            }
+            Value::Object(object).to_string()
+        }
+    }
+}
+
+/// Writes an event to stderr if its level is enabled
+///
+/// # Arguments
+///
+/// * `level` - Event level
+/// * `message` - Event message
+/// * `fields` - Event fields
+pub fn log(level: Level, message: &str, fields: &[(&str, Value)]) {
+    if !enabled(level) {
+        return;
+    }
+
+    let format = LOG_CONFIG.get().map_or(LogFormat::Text, |c| c.format);
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or_default();
+
+    eprintln!(
+        "{}",
+        format_event(format, timestamp, level, message, fields)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_event() {
+        let fields = [
+            ("method", Value::from("POST")),
+            ("url", Value::from("http://localhost/opsml/cards/list")),
+            ("status", Value::from(200)),
+            ("error", Value::from("connection refused")),
+        ];
+
+        assert_eq!(
+            format_event(LogFormat::Text, 1.5, Level::Info, "http request", &fields),
+            "1.500 info  http request method=POST url=http://localhost/opsml/cards/list status=200 error=\"connection refused\""
+        );
+
+        let json: Value = serde_json::from_str(&format_event(
+            LogFormat::Json,
+            1.5,
+            Level::Debug,
+            "http request",
+            &fields,
+        ))
+        .unwrap();
+        assert_eq!(json["level"], "debug");
+        assert_eq!(json["status"], 200);
+        assert_eq!(json["message"], "http request");
+    }
+
+    #[test]
+    fn test_redact_header() {
+        assert_eq!(redact_header("Authorization", "Bearer abc"), "[redacted]");
+        assert_eq!(redact_header("X-Opsml-Token", "abc"), "[redacted]");
+        assert_eq!(
+            redact_header("content-type", "application/json"),
+            "application/json"
+        );
+    }
+}
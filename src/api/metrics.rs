@@ -1,54 +1,795 @@
+use crate::api::compare_report::{render_junit, render_report, ReportFormat};
+use crate::api::compare_spec::{CompareSpec, MissingPolicy};
+use crate::api::output::{render_csv, OutputFormat};
+use crate::api::resolve;
 use crate::api::types;
 use crate::api::utils;
 use owo_colors::OwoColorize;
-use reqwest;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use regex::Regex;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::{fmt, fs};
 use tabled::settings::style::Style;
 use tabled::{settings::Alignment, Table};
 
-fn parse_metric_response(response: &str) -> String {
-    // Parses response and creates a table
+/// Unicode blocks used to draw sparklines, lowest first
+const SPARK_BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
 
-    let metrics: types::ListMetricResponse =
-        serde_json::from_str(response).expect("Failed to load response to MetricResponse JSON");
+/// Maximum number of characters in a metric sparkline
+const SPARKLINE_WIDTH: usize = 30;
 
-    let mut metric_table: Vec<types::MetricTable> = Vec::new();
+/// Renders values as a Unicode sparkline
+///
+/// Series longer than `width` are averaged into `width` buckets.
+///
+/// * `values` - values ordered by step
+/// * `width` - maximum number of characters
+fn sparkline(values: &[f64], width: usize) -> String {
+    if values.is_empty() || width == 0 {
+        return String::new();
+    }
 
-    for (_, metric_array) in metrics.metrics.iter() {
-        for metric in metric_array.iter() {
-            let step = if metric.step.is_some() {
-                metric.step.as_ref().unwrap().to_string()
-            } else {
-                "None".to_string()
-            };
+    let buckets = values.len().min(width);
+    let points: Vec<f64> = (0..buckets)
+        .map(|i| {
+            let bucket = &values[i * values.len() / buckets..(i + 1) * values.len() / buckets];
+            bucket.iter().sum::<f64>() / bucket.len() as f64
+        })
+        .collect();
+
+    let min = points.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = points.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let top = (SPARK_BLOCKS.len() - 1) as f64;
 
-            let timestamp = if metric.timestamp.is_some() {
-                metric.timestamp.as_ref().unwrap().to_string()
+    points
+        .iter()
+        .map(|point| {
+            if max > min {
+                SPARK_BLOCKS[((point - min) / (max - min) * top).round() as usize]
             } else {
-                "None".to_string()
-            };
+                SPARK_BLOCKS[SPARK_BLOCKS.len() / 2 - 1]
+            }
+        })
+        .collect()
+}
+
+/// Converts a glob with `*` and `?` wildcards to an anchored regex
+///
+/// * `glob` - metric name or glob (e.g. val_*)
+fn glob_to_regex(glob: &str) -> String {
+    let mut pattern = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            _ => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push('$');
+    pattern
+}
+
+/// Selects which logged metric values are shown
+#[derive(Debug, Default)]
+pub struct MetricFilter {
+    /// Metric name matchers built from names, globs and regexes
+    matchers: Vec<Regex>,
+    /// Smallest step to keep
+    min_step: Option<f64>,
+    /// Largest step to keep
+    max_step: Option<f64>,
+    /// Keep only the last step of each metric
+    latest_only: bool,
+}
+
+impl MetricFilter {
+    /// Builds a metric filter
+    ///
+    /// * `names` - metric names or globs
+    /// * `patterns` - metric name regexes
+    /// * `min_step` - smallest step to keep
+    /// * `max_step` - largest step to keep
+    /// * `latest_only` - keep only the last step of each metric
+    pub fn new(
+        names: &[String],
+        patterns: &[String],
+        min_step: Option<f64>,
+        max_step: Option<f64>,
+        latest_only: bool,
+    ) -> Result<Self, String> {
+        if let (Some(min_step), Some(max_step)) = (min_step, max_step) {
+            if min_step > max_step {
+                return Err(format!(
+                    "--min-step {} is greater than --max-step {}",
+                    min_step, max_step
+                ));
+            }
+        }
+
+        let mut matchers = Vec::new();
+        for name in names.iter() {
+            matchers.push(Regex::new(&glob_to_regex(name)).map_err(|e| e.to_string())?);
+        }
+        for pattern in patterns.iter() {
+            matchers.push(
+                Regex::new(pattern)
+                    .map_err(|e| format!("Invalid metric regex {}: {}", pattern, e))?,
+            );
+        }
+
+        Ok(MetricFilter {
+            matchers,
+            min_step,
+            max_step,
+            latest_only,
+        })
+    }
 
-            metric_table.push(types::MetricTable {
-                metric: metric.name.clone(),
-                value: metric.value.clone(),
-                step: step,
-                timestamp: timestamp,
+    fn matches_name(&self, name: &str) -> bool {
+        self.matchers.is_empty() || self.matchers.iter().any(|m| m.is_match(name))
+    }
+
+    fn matches_step(&self, step: Option<f64>) -> bool {
+        if self.min_step.is_none() && self.max_step.is_none() {
+            return true;
+        }
+
+        // values logged without a step cannot be placed in a step range
+        step.is_some_and(|step| {
+            self.min_step.is_none_or(|min| step >= min)
+                && self.max_step.is_none_or(|max| step <= max)
+        })
+    }
+
+    /// Filters logged metrics, grouping values by metric name in step order
+    ///
+    /// * `metrics` - metrics logged for a model
+    pub fn apply(&self, metrics: types::ListMetricResponse) -> types::ListMetricResponse {
+        let mut filtered: HashMap<String, Vec<types::Metric>> = HashMap::new();
+
+        for metric in metrics.metrics.into_values().flatten() {
+            let step = metric.step.as_ref().and_then(|step| step.as_f64());
+            if self.matches_name(&metric.name) && self.matches_step(step) {
+                filtered
+                    .entry(metric.name.clone())
+                    .or_default()
+                    .push(metric);
+            }
+        }
+
+        for values in filtered.values_mut() {
+            values.sort_by(|a, b| {
+                let step = |metric: &types::Metric| metric.step.as_ref().and_then(|s| s.as_f64());
+                step(a).partial_cmp(&step(b)).unwrap_or(Ordering::Equal)
             });
+
+            if self.latest_only {
+                let last = values.pop();
+                values.clear();
+                values.extend(last);
+            }
+        }
+
+        types::ListMetricResponse { metrics: filtered }
+    }
+}
+
+/// Summarises each metric as a single row
+///
+/// * `metrics` - metrics logged for a model
+/// * `names` - metric names to summarise
+fn summarize_metrics(
+    metrics: &types::ListMetricResponse,
+    names: &[String],
+) -> Vec<types::MetricSummaryTable> {
+    let format_value = |value: Option<f64>| match value {
+        Some(value) => value.to_string(),
+        None => "-".to_string(),
+    };
+
+    names
+        .iter()
+        .map(|name| {
+            let values = metric_series(metrics, name);
+            let steps = metrics.metrics.get(name).map_or(0, |values| values.len());
+
+            types::MetricSummaryTable {
+                metric: name.clone(),
+                count: steps,
+                min: format_value(values.iter().copied().reduce(f64::min)),
+                max: format_value(values.iter().copied().reduce(f64::max)),
+                last: format_value(values.last().copied()),
+                trend: sparkline(&values, SPARKLINE_WIDTH),
+            }
+        })
+        .collect()
+}
+
+/// Lists every logged value, ordered by metric and step
+///
+/// * `metrics` - filtered metrics, grouped by name in step order
+/// * `names` - sorted metric names
+fn ordered_metrics<'a>(
+    metrics: &'a types::ListMetricResponse,
+    names: &[String],
+) -> Vec<&'a types::Metric> {
+    names
+        .iter()
+        .filter_map(|name| metrics.metrics.get(name))
+        .flatten()
+        .collect()
+}
+
+/// Renders every logged value as a table row
+///
+/// * `metric` - logged metric value
+fn metric_table_row(metric: &types::Metric) -> types::MetricTable {
+    let step = match &metric.step {
+        Some(step) => step.to_string(),
+        None => "None".to_string(),
+    };
+
+    let timestamp = match &metric.timestamp {
+        Some(timestamp) => timestamp.to_string(),
+        None => "None".to_string(),
+    };
+
+    types::MetricTable {
+        metric: metric.name.clone(),
+        value: metric.value.clone(),
+        step,
+        timestamp,
+    }
+}
+
+/// Renders logged values as csv records
+///
+/// * `metrics` - logged metric values
+fn metric_csv(metrics: &[&types::Metric]) -> Result<String, String> {
+    let optional = |value: &Option<serde_json::Value>| match value {
+        Some(value) => value.to_string(),
+        None => String::new(),
+    };
+
+    let records: Vec<Vec<String>> = metrics
+        .iter()
+        .map(|metric| {
+            vec![
+                metric.name.clone(),
+                metric.value.to_string(),
+                optional(&metric.step),
+                optional(&metric.timestamp),
+            ]
+        })
+        .collect();
+
+    render_csv(&["metric", "value", "step", "timestamp"], &records)
+}
+
+fn parse_metric_response(
+    response: &str,
+    filter: &MetricFilter,
+    full: bool,
+    format: OutputFormat,
+) -> Result<String, String> {
+    // Parses response and renders the filtered metrics. Tables summarise each metric
+    // unless full is set, structured formats always list every value
+
+    let metrics: types::ListMetricResponse = serde_json::from_str(response)
+        .map_err(|e| format!("Failed to load response to MetricResponse JSON: {}", e))?;
+    let metrics = filter.apply(metrics);
+
+    let mut names: Vec<String> = metrics.metrics.keys().cloned().collect();
+    names.sort();
+
+    match format {
+        OutputFormat::Json => {
+            return serde_json::to_string_pretty(&ordered_metrics(&metrics, &names))
+                .map_err(|e| e.to_string())
         }
+        OutputFormat::Csv => return metric_csv(&ordered_metrics(&metrics, &names)),
+        OutputFormat::Table => {}
     }
 
-    let metric_table = Table::new(metric_table)
+    if names.is_empty() {
+        return Ok("No metrics found".to_string());
+    }
+
+    let mut table = if full {
+        Table::new(
+            ordered_metrics(&metrics, &names)
+                .into_iter()
+                .map(metric_table_row),
+        )
+    } else {
+        Table::new(summarize_metrics(&metrics, &names))
+    };
+
+    Ok(table
         .with(Alignment::center())
         .with(Style::sharp())
-        .to_string();
+        .to_string())
+}
+
+/// Criteria a challenger must meet to qualify for promotion
+struct CompareGate {
+    /// Fail if the challenger loses any battle
+    fail_on_loss: bool,
+    /// Minimum number of battles the challenger must win
+    require_wins: Option<usize>,
+    /// Relative amount (percent) the challenger may be worse by, per metric
+    tolerance: HashMap<String, f64>,
+    /// How battles against champions missing a metric are counted
+    missing: MissingPolicy,
+}
+
+/// Effective result of a single battle
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Win,
+    Loss,
+    Tolerated,
+    /// Not judged, counts as neither a win nor a loss
+    Skipped,
+}
+
+/// Battle report judged against the gate
+pub struct Battle<'a> {
+    pub metric: String,
+    pub report: &'a types::BattleReport,
+    pub outcome: Outcome,
+    pub lower_is_better: bool,
+}
+
+/// Extra detail printed alongside the comparison table
+#[derive(Debug, Default)]
+pub struct CompareOutput {
+    /// Summarise metrics logged over multiple steps
+    pub step_stats: bool,
+    /// Bootstrap resamples for the step mean delta confidence interval
+    pub bootstrap: Option<usize>,
+    /// Report format and the file to write it to
+    pub report: Option<(ReportFormat, String)>,
+    /// JUnit xml file to write
+    pub junit: Option<String>,
+}
+
+/// Judged comparison, as rendered into reports
+pub struct CompareResult<'a> {
+    pub challenger_name: &'a str,
+    pub challenger_version: &'a str,
+    pub battles: &'a [Battle<'a>],
+    pub summary: &'a CompareSummary,
+    pub qualified: bool,
+}
+
+/// Seed for bootstrap resampling, fixed so repeated runs print the same interval
+const BOOTSTRAP_SEED: u64 = 42;
+
+/// Outcome of all battles in a comparison
+#[derive(Debug, PartialEq)]
+pub struct CompareSummary {
+    pub wins: usize,
+    pub losses: usize,
+    pub tolerated: usize,
+    pub skipped: usize,
+}
+
+impl CompareSummary {
+    pub fn from_battles(battles: &[Battle]) -> Self {
+        let count = |outcome: Outcome| battles.iter().filter(|b| b.outcome == outcome).count();
+
+        CompareSummary {
+            wins: count(Outcome::Win),
+            losses: count(Outcome::Loss),
+            tolerated: count(Outcome::Tolerated),
+            skipped: count(Outcome::Skipped),
+        }
+    }
+
+    fn qualifies(&self, gate: &CompareGate) -> bool {
+        let wins_met = gate.require_wins.is_none_or(|wins| self.wins >= wins);
+        let no_loss = !gate.fail_on_loss || self.losses == 0;
+        wins_met && no_loss
+    }
+}
+
+/// Checks whether a lost battle is within the metric tolerance
+///
+/// * `champion_value` - champion metric value
+/// * `challenger_value` - challenger metric value
+/// * `lower_is_better` - metric direction
+/// * `tolerance` - relative amount (percent) the challenger may be worse by
+fn within_tolerance(
+    champion_value: f64,
+    challenger_value: f64,
+    lower_is_better: bool,
+    tolerance: f64,
+) -> bool {
+    let worse_by = if lower_is_better {
+        challenger_value - champion_value
+    } else {
+        champion_value - challenger_value
+    };
+
+    if worse_by <= 0.0 {
+        return true;
+    }
+
+    if champion_value == 0.0 {
+        return false;
+    }
+
+    worse_by / champion_value.abs() * 100.0 <= tolerance
+}
+
+/// Numeric champion and challenger values of a battle, if both were logged
+///
+/// * `report` - battle report
+fn battle_values(report: &types::BattleReport) -> Option<(f64, f64)> {
+    let champion_value = report.champion_metric.as_ref()?.value.as_f64()?;
+    let challenger_value = report.challenger_metric.as_ref()?.value.as_f64()?;
+    Some((champion_value, challenger_value))
+}
+
+/// Difference between the challenger and champion value of a metric
+#[derive(Debug, PartialEq)]
+pub struct MetricDelta {
+    /// Challenger value minus champion value
+    pub delta: f64,
+    /// Delta relative to the champion value (percent)
+    pub change: Option<f64>,
+    /// Relative improvement of the challenger (percent), negative when worse
+    pub better_by: Option<f64>,
+}
+
+impl MetricDelta {
+    fn new(champion_value: f64, challenger_value: f64, lower_is_better: bool) -> Self {
+        let delta = challenger_value - champion_value;
+        let change = if champion_value == 0.0 {
+            None
+        } else {
+            Some(delta / champion_value.abs() * 100.0)
+        };
+        let better_by = change.map(|change| if lower_is_better { -change } else { change });
+
+        MetricDelta {
+            delta,
+            change,
+            better_by,
+        }
+    }
+
+    /// Renders the delta, relative change and improvement without colour
+    pub fn cells(&self) -> [String; 3] {
+        let percent = |value: Option<f64>| match value {
+            Some(value) => format!("{}%", format_signed(value)),
+            None => "-".to_string(),
+        };
+
+        [
+            format_signed(self.delta),
+            percent(self.change),
+            percent(self.better_by),
+        ]
+    }
+}
+
+/// Difference between the challenger and champion value of a battle
+///
+/// * `battle` - judged battle
+pub fn battle_delta(battle: &Battle) -> Option<MetricDelta> {
+    battle_values(battle.report).map(|(champion_value, challenger_value)| {
+        MetricDelta::new(champion_value, challenger_value, battle.lower_is_better)
+    })
+}
+
+/// Renders a number with at most four decimals
+///
+/// * `value` - number to render
+pub fn format_number(value: f64) -> String {
+    let formatted = format!("{:.4}", value);
+    let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+
+    match trimmed {
+        "-0" => "0".to_string(),
+        _ => trimmed.to_string(),
+    }
+}
+
+/// Renders a number with an explicit sign for positive values
+///
+/// * `value` - number to render
+pub fn format_signed(value: f64) -> String {
+    let formatted = format_number(value);
+    if formatted.starts_with('-') || formatted == "0" {
+        formatted
+    } else {
+        format!("+{}", formatted)
+    }
+}
+
+/// Summary of a metric logged over multiple steps
+#[derive(Debug, PartialEq)]
+struct StepStats {
+    steps: usize,
+    last: f64,
+    best: f64,
+    mean: f64,
+}
+
+impl StepStats {
+    /// Summarises metric values ordered by step
+    ///
+    /// * `values` - metric values ordered by step
+    /// * `lower_is_better` - metric direction
+    fn from_values(values: &[f64], lower_is_better: bool) -> Option<Self> {
+        let last = *values.last()?;
+        let best = values.iter().copied().fold(last, |best, value| {
+            let better = if lower_is_better {
+                value < best
+            } else {
+                value > best
+            };
+            if better {
+                value
+            } else {
+                best
+            }
+        });
 
-    return metric_table;
+        Some(StepStats {
+            steps: values.len(),
+            last,
+            best,
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+        })
+    }
+}
+
+impl fmt::Display for StepStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} / {} / {}",
+            format_number(self.last),
+            format_number(self.best),
+            format_number(self.mean)
+        )
+    }
+}
+
+/// Numeric values of a metric with their step, ordered by step
+///
+/// * `metrics` - metrics logged for a model
+/// * `name` - metric name
+pub fn metric_points(metrics: &types::ListMetricResponse, name: &str) -> Vec<(Option<f64>, f64)> {
+    let mut points: Vec<(Option<f64>, f64)> = metrics
+        .metrics
+        .values()
+        .flatten()
+        .filter(|metric| metric.name == name)
+        .filter_map(|metric| {
+            let step = metric.step.as_ref().and_then(|step| step.as_f64());
+            metric.value.as_f64().map(|value| (step, value))
+        })
+        .collect();
+
+    points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+    points
+}
+
+/// Numeric values of a metric ordered by step
+///
+/// * `metrics` - metrics logged for a model
+/// * `name` - metric name
+pub fn metric_series(metrics: &types::ListMetricResponse, name: &str) -> Vec<f64> {
+    metric_points(metrics, name)
+        .into_iter()
+        .map(|(_, value)| value)
+        .collect()
 }
 
-fn parse_compare_metric_response(response: &str) -> String {
-    // Parses response and creates a table
+/// Mean of a sample drawn with replacement
+///
+/// * `values` - values to resample
+/// * `rng` - random number generator
+fn resample_mean<R: Rng>(values: &[f64], rng: &mut R) -> f64 {
+    let total: f64 = (0..values.len())
+        .map(|_| values[rng.gen_range(0..values.len())])
+        .sum();
+    total / values.len() as f64
+}
+
+/// Bootstrap 95% confidence interval for the challenger mean minus the champion mean
+///
+/// * `champion` - champion values
+/// * `challenger` - challenger values
+/// * `resamples` - number of bootstrap resamples
+/// * `rng` - random number generator
+fn bootstrap_mean_delta<R: Rng>(
+    champion: &[f64],
+    challenger: &[f64],
+    resamples: usize,
+    rng: &mut R,
+) -> Option<(f64, f64)> {
+    if champion.is_empty() || challenger.is_empty() || resamples == 0 {
+        return None;
+    }
+
+    let mut deltas: Vec<f64> = (0..resamples)
+        .map(|_| resample_mean(challenger, rng) - resample_mean(champion, rng))
+        .collect();
+    deltas.sort_by(f64::total_cmp);
 
-    let compare_report: types::CompareMetricResponse = serde_json::from_str(response)
-        .expect("Failed to load response to CompareMetricResponse JSON");
+    let lower = (resamples as f64 * 0.025).floor() as usize;
+    let upper = (resamples as f64 * 0.975).ceil() as usize;
+
+    Some((
+        deltas[lower.min(resamples - 1)],
+        deltas[upper.clamp(1, resamples) - 1],
+    ))
+}
+
+/// Judges a single battle
+///
+/// A challenger missing a metric always loses. A champion missing a metric is
+/// counted according to the missing metric policy, skipped battles are neither
+/// won nor lost.
+///
+/// * `metric` - metric name
+/// * `report` - battle report
+/// * `directions` - lower_is_better per metric name
+/// * `gate` - qualification criteria
+fn judge_battle(
+    metric: &str,
+    report: &types::BattleReport,
+    directions: &HashMap<String, bool>,
+    gate: &CompareGate,
+) -> Result<Outcome, String> {
+    if report.challenger_metric.is_none() {
+        return Ok(Outcome::Loss);
+    }
+
+    if report.champion_metric.is_none() {
+        return match gate.missing {
+            MissingPolicy::Skip => Ok(Outcome::Skipped),
+            MissingPolicy::Warn => Ok(Outcome::Loss),
+            MissingPolicy::Fail => Err(format!(
+                "Champion {} {} is missing metric {}",
+                report.champion_name, report.champion_version, metric
+            )),
+        };
+    }
+
+    if report.challenger_win {
+        return Ok(Outcome::Win);
+    }
+
+    let tolerated = match (battle_values(report), gate.tolerance.get(metric)) {
+        (Some((champion_value, challenger_value)), Some(tolerance)) => within_tolerance(
+            champion_value,
+            challenger_value,
+            *directions.get(metric).unwrap_or(&true),
+            *tolerance,
+        ),
+        _ => false,
+    };
+
+    if tolerated {
+        Ok(Outcome::Tolerated)
+    } else {
+        Ok(Outcome::Loss)
+    }
+}
+
+/// Judges every battle in a comparison, ordered by metric and champion
+///
+/// * `compare_report` - comparison response
+/// * `directions` - lower_is_better per metric name
+/// * `gate` - qualification criteria
+fn judge_battles<'a>(
+    compare_report: &'a types::CompareMetricResponse,
+    directions: &HashMap<String, bool>,
+    gate: &CompareGate,
+) -> Result<Vec<Battle<'a>>, String> {
+    let mut battles = Vec::new();
+
+    for (metric, reports) in compare_report.report.iter() {
+        for report in reports.iter() {
+            battles.push(Battle {
+                metric: metric.clone(),
+                report,
+                outcome: judge_battle(metric, report, directions, gate)?,
+                lower_is_better: *directions.get(metric).unwrap_or(&true),
+            });
+        }
+    }
+
+    battles.sort_by(|a, b| {
+        (
+            &a.metric,
+            &a.report.champion_name,
+            &a.report.champion_version,
+        )
+            .cmp(&(
+                &b.metric,
+                &b.report.champion_name,
+                &b.report.champion_version,
+            ))
+    });
+
+    Ok(battles)
+}
+
+/// Lists the metrics each champion or the challenger did not log
+///
+/// * `battles` - judged battles
+pub fn find_missing_metrics(battles: &[Battle]) -> Vec<String> {
+    let mut missing: Vec<(String, Vec<String>)> = Vec::new();
+
+    let mut add = |model: String, metric: &str| match missing.iter_mut().find(|(m, _)| *m == model)
+    {
+        Some((_, metrics)) if !metrics.iter().any(|m| m == metric) => {
+            metrics.push(metric.to_string())
+        }
+        Some(_) => {}
+        None => missing.push((model, vec![metric.to_string()])),
+    };
+
+    for battle in battles.iter() {
+        let report = battle.report;
+        if report.challenger_metric.is_none() {
+            add("Challenger".to_string(), &battle.metric);
+        }
+        if report.champion_metric.is_none() {
+            let champion = format!(
+                "Champion {} {}",
+                report.champion_name, report.champion_version
+            );
+            add(champion, &battle.metric);
+        }
+    }
+
+    missing
+        .into_iter()
+        .map(|(model, metrics)| format!("{} is missing: {}", model, metrics.join(", ")))
+        .collect()
+}
+
+/// Renders a metric value, marking metrics that were not logged
+///
+/// * `metric` - optional metric
+fn format_metric_value(metric: &Option<types::Metric>) -> String {
+    match metric {
+        Some(metric) => metric.value.to_string(),
+        None => "missing".yellow().to_string(),
+    }
+}
+
+/// Renders the delta, relative change and improvement of a battle
+///
+/// * `battle` - judged battle
+fn format_battle_delta(battle: &Battle) -> Vec<String> {
+    let delta = match battle_delta(battle) {
+        Some(delta) => delta,
+        None => return vec!["-".to_string(); 3],
+    };
+
+    let [delta_cell, change, better_by] = delta.cells();
+    let better_by = match delta.better_by {
+        Some(value) if value > 0.0 => better_by.green().to_string(),
+        Some(value) if value < 0.0 => better_by.red().to_string(),
+        _ => better_by,
+    };
+
+    vec![delta_cell, change, better_by]
+}
+
+fn parse_compare_metric_response(battles: &[Battle]) -> String {
+    // Creates a table from judged battles
 
     let mut builder = tabled::builder::Builder::default();
     builder.set_header(vec![
@@ -57,35 +798,31 @@ fn parse_compare_metric_response(response: &str) -> String {
         "Metric",
         "Champion Value",
         "Challenger Value",
+        "Delta",
+        "Change",
+        "Better By",
         "Challenger Win",
     ]);
 
-    let battle_reports = compare_report.report;
-    for (_, reports) in battle_reports.iter() {
-        for report in reports.iter() {
-            // champion and challenger should have metrics to render in table
-            if report.champion_metric.is_none() && report.challenger_metric.is_none() {
-                continue;
-            } else {
-                let challenger_metric = report.challenger_metric.as_ref().unwrap();
-                let champion_metric = report.champion_metric.as_ref().unwrap();
-                let mut record = vec![
-                    report.champion_name.clone(),
-                    report.champion_version.clone(),
-                    champion_metric.name.clone(),
-                    champion_metric.value.to_string(),
-                    challenger_metric.value.to_string(),
-                ];
-
-                if report.challenger_win == true {
-                    record.append(&mut vec!["true".green().to_string()]);
-                } else {
-                    record.append(&mut vec!["false".red().to_string()]);
-                };
-                // insert values
-                builder.push_record(record);
-            }
-        }
+    for battle in battles.iter() {
+        let report = battle.report;
+        let mut record = vec![
+            report.champion_name.clone(),
+            report.champion_version.clone(),
+            battle.metric.clone(),
+            format_metric_value(&report.champion_metric),
+            format_metric_value(&report.challenger_metric),
+        ];
+        record.extend(format_battle_delta(battle));
+
+        match battle.outcome {
+            Outcome::Win => record.push("true".green().to_string()),
+            Outcome::Tolerated => record.push("tolerated".yellow().to_string()),
+            Outcome::Loss => record.push("false".red().to_string()),
+            Outcome::Skipped => record.push("skipped".dimmed().to_string()),
+        };
+        // insert values
+        builder.push_record(record);
     }
 
     let mut table = builder.build();
@@ -94,7 +831,133 @@ fn parse_compare_metric_response(response: &str) -> String {
         .with(Style::sharp())
         .to_string();
 
-    return compare_metric_table;
+    compare_metric_table
+}
+
+/// Creates a table summarising battles over metrics logged at multiple steps
+///
+/// Returns None when no compared metric was logged at more than one step.
+///
+/// * `battles` - judged battles
+/// * `challenger` - metrics logged for the challenger
+/// * `champions` - metrics logged for each champion, by name and version
+/// * `bootstrap` - bootstrap resamples for the mean delta confidence interval
+fn parse_step_stats(
+    battles: &[Battle],
+    challenger: &types::ListMetricResponse,
+    champions: &HashMap<(String, String), types::ListMetricResponse>,
+    bootstrap: Option<usize>,
+) -> Option<String> {
+    let mut rng = StdRng::seed_from_u64(BOOTSTRAP_SEED);
+
+    let mut header = vec![
+        "Champion Name",
+        "Champion Version",
+        "Metric",
+        "Steps",
+        "Champion Last / Best / Mean",
+        "Challenger Last / Best / Mean",
+        "Mean Delta",
+    ];
+    if bootstrap.is_some() {
+        header.extend(["95% CI", "Signal"]);
+    }
+
+    let mut builder = tabled::builder::Builder::default();
+    builder.set_header(header);
+    let mut rows = 0;
+
+    for battle in battles.iter() {
+        let report = battle.report;
+        let key = (
+            report.champion_name.clone(),
+            report.champion_version.clone(),
+        );
+        let champion_values = match champions.get(&key) {
+            Some(metrics) => metric_series(metrics, &battle.metric),
+            None => continue,
+        };
+        let challenger_values = metric_series(challenger, &battle.metric);
+
+        let stats = StepStats::from_values(&champion_values, battle.lower_is_better).zip(
+            StepStats::from_values(&challenger_values, battle.lower_is_better),
+        );
+        let (champion_stats, challenger_stats) = match stats {
+            Some(stats) if stats.0.steps > 1 || stats.1.steps > 1 => stats,
+            _ => continue,
+        };
+
+        let mut record = vec![
+            report.champion_name.clone(),
+            report.champion_version.clone(),
+            battle.metric.clone(),
+            format!("{} / {}", champion_stats.steps, challenger_stats.steps),
+            champion_stats.to_string(),
+            challenger_stats.to_string(),
+            format_signed(challenger_stats.mean - champion_stats.mean),
+        ];
+
+        if let Some(resamples) = bootstrap {
+            match bootstrap_mean_delta(&champion_values, &challenger_values, resamples, &mut rng) {
+                Some((lower, upper)) => {
+                    // an interval excluding zero is unlikely to be noise
+                    let (improved, regressed) = if battle.lower_is_better {
+                        (upper < 0.0, lower > 0.0)
+                    } else {
+                        (lower > 0.0, upper < 0.0)
+                    };
+                    let signal = if improved {
+                        "improvement".green().to_string()
+                    } else if regressed {
+                        "regression".red().to_string()
+                    } else {
+                        "noise".yellow().to_string()
+                    };
+                    record.push(format!(
+                        "[{}, {}]",
+                        format_signed(lower),
+                        format_signed(upper)
+                    ));
+                    record.push(signal);
+                }
+                None => record.extend(["-".to_string(), "-".to_string()]),
+            }
+        }
+
+        builder.push_record(record);
+        rows += 1;
+    }
+
+    if rows == 0 {
+        return None;
+    }
+
+    let mut table = builder.build();
+    Some(
+        table
+            .with(Alignment::center())
+            .with(Style::sharp())
+            .to_string(),
+    )
+}
+
+/// Fetches the metrics logged for a model
+///
+/// # Arguments
+///
+/// * `request` - Card name and version, or uid
+pub async fn fetch_model_metrics(
+    request: &types::CardRequest,
+) -> Result<types::ListMetricResponse, utils::RequestError> {
+    let response = utils::make_post_request(&utils::OpsmlPaths::Metric.as_str()?, request).await?;
+
+    if !response.status().is_success() {
+        return Err(format!("Failed to get metrics for model: {}", response.status()).into());
+    }
+
+    let text = response.text().await.map_err(|e| e.to_string())?;
+    Ok(serde_json::from_str(&text)
+        .map_err(|e| format!("Failed to load response to MetricResponse JSON: {}", e))?)
 }
 
 /// List all metrics for a model
@@ -104,13 +967,18 @@ fn parse_compare_metric_response(response: &str) -> String {
 /// * `name` - Name of the model
 /// * `version` - Version of the model
 /// * `uid` - Unique identifier of the model
-/// * `url` - URL of the OpsML server
+/// * `filter` - Metrics and steps to show
+/// * `full` - Show every logged step instead of a summary per metric
+/// * `format` - Output format
 #[tokio::main]
 pub async fn get_model_metrics(
     name: Option<&str>,
     version: Option<&str>,
     uid: Option<&str>,
-) -> Result<(), reqwest::Error> {
+    filter: &MetricFilter,
+    full: bool,
+    format: OutputFormat,
+) -> Result<(), utils::RequestError> {
     let model_metric_request = types::CardRequest {
         name: name.map(|s| s.to_string()),
         version: version.map(|s| s.to_string()),
@@ -118,55 +986,234 @@ pub async fn get_model_metrics(
     };
 
     let response =
-        utils::make_post_request(&utils::OpsmlPaths::Metric.as_str(), &model_metric_request).await;
+        utils::make_post_request(&utils::OpsmlPaths::Metric.as_str()?, &model_metric_request)
+            .await?;
 
     if response.status().is_success() {
-        let metric_table = parse_metric_response(&response.text().await?);
-        println!("{}", metric_table);
+        let text = response.text().await.map_err(|e| e.to_string())?;
+        let metric_table = parse_metric_response(&text, filter, full, format)?;
+        println!("{}", metric_table.trim_end());
     } else {
-        println!("Failed to get metrics for model");
-        response.error_for_status_ref()?;
+        eprintln!("Failed to get metrics for model");
+        response.error_for_status_ref().map_err(|e| e.to_string())?;
     }
 
     Ok(())
 }
 
+/// Why a comparison could not be completed
+#[derive(Debug, PartialEq)]
+pub enum CompareError {
+    /// The compare route failed or returned an unreadable comparison
+    Server(String),
+    /// Any other failure, such as an unresolvable model
+    Other(String),
+    /// The compare request was printed by --dry-run instead of being sent
+    DryRun,
+}
+
+impl From<utils::RequestError> for CompareError {
+    fn from(e: utils::RequestError) -> Self {
+        match e {
+            utils::RequestError::DryRun => CompareError::DryRun,
+            utils::RequestError::Failed(e) => CompareError::Other(e),
+        }
+    }
+}
+
+impl From<String> for CompareError {
+    fn from(e: String) -> Self {
+        CompareError::Other(e)
+    }
+}
+
+impl From<&str> for CompareError {
+    fn from(e: &str) -> Self {
+        CompareError::Other(e.to_string())
+    }
+}
+
+impl fmt::Display for CompareError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompareError::Server(e) | CompareError::Other(e) => write!(f, "{}", e),
+            CompareError::DryRun => write!(f, "{}", utils::RequestError::DryRun),
+        }
+    }
+}
+
+/// Compare challenger metrics against one or more champions
+///
+/// Returns whether the challenger qualifies according to the spec
+///
+/// # Arguments
+///
+/// * `spec` - Validated comparison spec
+/// * `output` - Extra detail to print
 #[tokio::main]
 pub async fn compare_model_metrics(
-    metric_name: &Vec<String>,
-    lower_is_better: &Vec<bool>,
-    challenger_uid: &str,
-    champion_uid: &Vec<String>,
-) -> Result<(), reqwest::Error> {
+    spec: &CompareSpec,
+    output: &CompareOutput,
+) -> Result<bool, CompareError> {
+    let challenger = spec.challenger.as_ref().ok_or("No challenger specified")?;
+    let challenger_uid = resolve::resolve_uid(challenger).await?;
+
+    // a selector such as name@last:3 may include the challenger itself
+    let mut champion_uid: Vec<String> = Vec::new();
+    for champion in spec.champions.iter() {
+        for uid in resolve::resolve_uids(champion).await? {
+            if uid != challenger_uid && !champion_uid.contains(&uid) {
+                champion_uid.push(uid);
+            }
+        }
+    }
+
+    if champion_uid.is_empty() {
+        return Err("No champions to compare the challenger against".into());
+    }
+
     // set up repair request
     let compare_metric_request = types::CompareMetricRequest {
-        metric_name: metric_name.clone(),
-        lower_is_better: lower_is_better.clone(),
-        challenger_uid: challenger_uid.to_string(),
-        champion_uid: champion_uid.clone(),
+        metric_name: spec.metric_names(),
+        lower_is_better: spec.lower_is_better(),
+        challenger_uid: challenger_uid.clone(),
+        champion_uid,
     };
 
     let response = utils::make_post_request(
-        &utils::OpsmlPaths::CompareMetric.as_str(),
+        &utils::OpsmlPaths::CompareMetric.as_str()?,
         &compare_metric_request,
     )
-    .await;
+    .await
+    .map_err(|e| match e {
+        utils::RequestError::DryRun => CompareError::DryRun,
+        utils::RequestError::Failed(e) => CompareError::Server(e),
+    })?;
 
-    if response.status().is_success() {
-        let metric_table = parse_compare_metric_response(&response.text().await?);
-        println!("{}", metric_table);
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(CompareError::Server(format!(
+            "Failed to compare metrics: {} {}",
+            status,
+            body.trim()
+        )));
+    }
+
+    let text = response
+        .text()
+        .await
+        .map_err(|e| CompareError::Server(e.to_string()))?;
+    let compare_report: types::CompareMetricResponse = serde_json::from_str(&text)
+        .map_err(|e| CompareError::Server(format!("Failed to parse comparison: {}", e)))?;
+
+    let directions: HashMap<String, bool> = spec
+        .metrics
+        .iter()
+        .map(|m| (m.name.clone(), m.direction.lower_is_better()))
+        .collect();
+
+    let gate = CompareGate {
+        fail_on_loss: spec.fail_on_loss,
+        require_wins: spec.require_wins,
+        tolerance: spec
+            .metrics
+            .iter()
+            .filter_map(|m| m.tolerance.map(|t| (m.name.clone(), t)))
+            .collect(),
+        missing: spec.missing,
+    };
+
+    let battles = judge_battles(&compare_report, &directions, &gate)?;
+    let metric_table = parse_compare_metric_response(&battles);
+    println!("{}", metric_table);
+
+    if output.step_stats {
+        let challenger_metrics = fetch_model_metrics(&types::CardRequest {
+            name: None,
+            version: None,
+            uid: Some(challenger_uid),
+        })
+        .await?;
+
+        let mut champion_metrics = HashMap::new();
+        for battle in battles.iter() {
+            let key = (
+                battle.report.champion_name.clone(),
+                battle.report.champion_version.clone(),
+            );
+            if champion_metrics.contains_key(&key) {
+                continue;
+            }
+            let metrics = fetch_model_metrics(&types::CardRequest {
+                name: Some(key.0.clone()),
+                version: Some(key.1.clone()),
+                uid: None,
+            })
+            .await?;
+            champion_metrics.insert(key, metrics);
+        }
+
+        match parse_step_stats(
+            &battles,
+            &challenger_metrics,
+            &champion_metrics,
+            output.bootstrap,
+        ) {
+            Some(step_table) => println!("{}", step_table),
+            None => println!("No compared metric was logged at more than one step"),
+        }
+    }
+
+    for missing in find_missing_metrics(&battles) {
+        println!("{}", missing.yellow());
+    }
+
+    let summary = CompareSummary::from_battles(&battles);
+    let qualified = summary.qualifies(&gate);
+    let verdict = if qualified {
+        "qualified".green().to_string()
     } else {
-        println!("Failed to get metrics for model");
-        response.error_for_status_ref()?;
+        "not qualified".red().to_string()
+    };
+
+    println!(
+        "Challenger {} {}: {} wins, {} losses, {} within tolerance, {} skipped - {}",
+        compare_report.challenger_name,
+        compare_report.challenger_version,
+        summary.wins,
+        summary.losses,
+        summary.tolerated,
+        summary.skipped,
+        verdict
+    );
+
+    let result = CompareResult {
+        challenger_name: &compare_report.challenger_name,
+        challenger_version: &compare_report.challenger_version,
+        battles: &battles,
+        summary: &summary,
+        qualified,
+    };
+
+    if let Some((format, path)) = &output.report {
+        fs::write(path, render_report(*format, &result))
+            .map_err(|e| format!("Failed to write report {}: {}", path, e))?;
+        println!("Wrote comparison report to {}", path);
     }
 
-    Ok(())
+    if let Some(path) = &output.junit {
+        fs::write(path, render_junit(&result))
+            .map_err(|e| format!("Failed to write junit report {}: {}", path, e))?;
+        println!("Wrote junit report to {}", path);
+    }
+
+    Ok(qualified)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::collections::HashMap;
 
     #[test]
     fn test_parse_metric_response() {
@@ -190,10 +1237,28 @@ mod tests {
         let mut metrics = HashMap::new();
         metrics.insert("test".to_string(), vec);
 
-        let mock_response = types::ListMetricResponse { metrics: metrics };
+        let mock_response = types::ListMetricResponse { metrics };
         let string_response = serde_json::to_string(&mock_response).unwrap();
 
-        let metric_table = parse_metric_response(&string_response);
+        let filter = MetricFilter::default();
+        let metric_table =
+            parse_metric_response(&string_response, &filter, false, OutputFormat::Table).unwrap();
+
+        assert_eq!(
+            metric_table,
+            concat!(
+                "┌────────┬───────┬─────┬─────┬──────┬───────┐\n",
+                "│ metric │ count │ min │ max │ last │ trend │\n",
+                "├────────┼───────┼─────┼─────┼──────┼───────┤\n",
+                "│  mae   │   1   │  5  │  5  │  5   │   ▄   │\n",
+                "│  mape  │   1   │ 10  │ 10  │  10  │   ▄   │\n",
+                "└────────┴───────┴─────┴─────┴──────┴───────┘",
+            )
+        );
+
+        let filter = MetricFilter::new(&["mape".to_string()], &[], None, None, false).unwrap();
+        let metric_table =
+            parse_metric_response(&string_response, &filter, true, OutputFormat::Table).unwrap();
 
         assert_eq!(
             metric_table,
@@ -201,11 +1266,81 @@ mod tests {
                 "┌────────┬───────┬──────┬───────────┐\n",
                 "│ metric │ value │ step │ timestamp │\n",
                 "├────────┼───────┼──────┼───────────┤\n",
-                "│  mae   │   5   │ None │   None    │\n",
                 "│  mape  │ 10.0  │ None │   None    │\n",
                 "└────────┴───────┴──────┴───────────┘",
             )
-        )
+        );
+
+        assert_eq!(
+            parse_metric_response(&string_response, &filter, false, OutputFormat::Csv).unwrap(),
+            "metric,value,step,timestamp\nmape,10.0,,\n"
+        );
+
+        let filter = MetricFilter::new(&["auc".to_string()], &[], None, None, false).unwrap();
+        assert_eq!(
+            parse_metric_response(&string_response, &filter, false, OutputFormat::Table).unwrap(),
+            "No metrics found"
+        );
+    }
+
+    #[test]
+    fn test_metric_filter() {
+        let metric = |name: &str, step: Option<i64>, value: f64| types::Metric {
+            name: name.to_string(),
+            value: value.into(),
+            step: step.map(|step| step.into()),
+            timestamp: None,
+        };
+        let metrics = || types::ListMetricResponse {
+            metrics: HashMap::from([
+                (
+                    "val_loss".to_string(),
+                    vec![
+                        metric("val_loss", Some(2), 0.3),
+                        metric("val_loss", Some(0), 0.9),
+                        metric("val_loss", Some(1), 0.5),
+                    ],
+                ),
+                ("val_auc".to_string(), vec![metric("val_auc", None, 0.8)]),
+                ("loss".to_string(), vec![metric("loss", Some(0), 1.0)]),
+            ]),
+        };
+        let names = |metrics: &types::ListMetricResponse| {
+            let mut names: Vec<String> = metrics.metrics.keys().cloned().collect();
+            names.sort();
+            names
+        };
+
+        let filter = MetricFilter::new(&["val_*".to_string()], &[], None, None, false).unwrap();
+        let filtered = filter.apply(metrics());
+        assert_eq!(names(&filtered), vec!["val_auc", "val_loss"]);
+        assert_eq!(metric_series(&filtered, "val_loss"), vec![0.9, 0.5, 0.3]);
+
+        let filter = MetricFilter::new(&[], &["loss$".to_string()], Some(1.0), None, true).unwrap();
+        let filtered = filter.apply(metrics());
+        assert_eq!(names(&filtered), vec!["val_loss"]);
+        assert_eq!(metric_series(&filtered, "val_loss"), vec![0.3]);
+
+        let filter = MetricFilter::new(&["loss".to_string()], &[], None, Some(0.0), false).unwrap();
+        assert_eq!(names(&filter.apply(metrics())), vec!["loss"]);
+
+        assert!(MetricFilter::new(&[], &["(".to_string()], None, None, false).is_err());
+        assert!(MetricFilter::new(&[], &[], Some(2.0), Some(1.0), false).is_err());
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(
+            sparkline(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0], 30),
+            "▁▂▃▄▅▆▇█"
+        );
+        assert_eq!(sparkline(&[8.0, 1.0], 30), "█▁");
+        assert_eq!(sparkline(&[2.0, 2.0, 2.0], 30), "▄▄▄");
+        assert_eq!(sparkline(&[], 30), "");
+
+        // long series are averaged into buckets
+        let values: Vec<f64> = (0..500).map(|step| step as f64).collect();
+        assert_eq!(sparkline(&values, 30).chars().count(), 30);
     }
 
     #[test]
@@ -252,10 +1387,185 @@ mod tests {
         let compare_response = types::CompareMetricResponse {
             challenger_name: "hootie-and-the-blowfish".to_string(),
             challenger_version: "1.0.0".to_string(),
-            report: report,
+            report,
         };
         let string_response = serde_json::to_string(&compare_response).unwrap();
+        let compare_response: types::CompareMetricResponse =
+            serde_json::from_str(&string_response).unwrap();
+
+        let directions = HashMap::from([("test".to_string(), true)]);
+        let mut gate = CompareGate {
+            fail_on_loss: true,
+            require_wins: None,
+            tolerance: HashMap::new(),
+            missing: MissingPolicy::Warn,
+        };
+
+        let battles = judge_battles(&compare_response, &directions, &gate).unwrap();
+        parse_compare_metric_response(&battles);
+
+        let summary = CompareSummary::from_battles(&battles);
+        assert_eq!(
+            summary,
+            CompareSummary {
+                wins: 1,
+                losses: 1,
+                tolerated: 0,
+                skipped: 0
+            }
+        );
+        assert!(!summary.qualifies(&gate));
+
+        // challenger (5) is 150% worse than the second champion (2)
+        gate.tolerance.insert("test".to_string(), 150.0);
+        let battles = judge_battles(&compare_response, &directions, &gate).unwrap();
+        let summary = CompareSummary::from_battles(&battles);
+        assert_eq!(summary.tolerated, 1);
+        assert!(summary.qualifies(&gate));
+
+        gate.require_wins = Some(2);
+        assert!(!summary.qualifies(&gate));
+    }
+
+    #[test]
+    fn test_missing_champion_metric() {
+        let metric = types::Metric {
+            name: "mae".to_string(),
+            value: 5.into(),
+            step: None,
+            timestamp: None,
+        };
+
+        let report = vec![
+            types::BattleReport {
+                champion_name: "model".to_string(),
+                champion_version: "1.0.0".to_string(),
+                champion_metric: None,
+                challenger_metric: Some(metric.clone()),
+                challenger_win: false,
+            },
+            types::BattleReport {
+                champion_name: "model".to_string(),
+                champion_version: "1.1.0".to_string(),
+                champion_metric: Some(metric),
+                challenger_metric: None,
+                challenger_win: false,
+            },
+        ];
+        let compare_response = types::CompareMetricResponse {
+            challenger_name: "model".to_string(),
+            challenger_version: "1.2.0".to_string(),
+            report: HashMap::from([("mae".to_string(), report)]),
+        };
+
+        let directions = HashMap::new();
+        let mut gate = CompareGate {
+            fail_on_loss: true,
+            require_wins: None,
+            tolerance: HashMap::new(),
+            missing: MissingPolicy::Skip,
+        };
+
+        // one-sided reports render instead of panicking
+        let battles = judge_battles(&compare_response, &directions, &gate).unwrap();
+        let outcomes: Vec<Outcome> = battles.iter().map(|b| b.outcome).collect();
+        assert_eq!(outcomes, vec![Outcome::Skipped, Outcome::Loss]);
+
+        // skipped battles do not count towards the required wins
+        gate.require_wins = Some(1);
+        let summary = CompareSummary::from_battles(&battles);
+        assert_eq!((summary.wins, summary.skipped), (0, 1));
+        assert!(!summary.qualifies(&gate));
+        gate.require_wins = None;
+        assert!(parse_compare_metric_response(&battles).contains("missing"));
+        assert_eq!(
+            find_missing_metrics(&battles),
+            vec![
+                "Champion model 1.0.0 is missing: mae",
+                "Challenger is missing: mae"
+            ]
+        );
+
+        gate.missing = MissingPolicy::Warn;
+        let battles = judge_battles(&compare_response, &directions, &gate).unwrap();
+        assert_eq!(battles[0].outcome, Outcome::Loss);
+
+        gate.missing = MissingPolicy::Fail;
+        assert_eq!(
+            judge_battles(&compare_response, &directions, &gate).err(),
+            Some("Champion model 1.0.0 is missing metric mae".to_string())
+        );
+    }
+
+    #[test]
+    fn test_metric_delta() {
+        // lower is better: 5 -> 4 is a 20% improvement
+        let delta = MetricDelta::new(5.0, 4.0, true);
+        assert_eq!(delta.delta, -1.0);
+        assert_eq!(delta.change, Some(-20.0));
+        assert_eq!(delta.better_by, Some(20.0));
+
+        // higher is better: 0.8 -> 0.6 is a 25% regression
+        let delta = MetricDelta::new(0.8, 0.6, false);
+        assert_eq!(format_signed(delta.delta), "-0.2");
+        assert_eq!(format_signed(delta.better_by.unwrap()), "-25");
+
+        assert_eq!(MetricDelta::new(0.0, 1.0, true).change, None);
+        assert_eq!(format_signed(1.23456), "+1.2346");
+        assert_eq!(format_signed(0.0), "0");
+        assert_eq!(format_number(10.0), "10");
+    }
+
+    #[test]
+    fn test_step_stats() {
+        let loss: Vec<types::Metric> = [(2, 0.3), (0, 0.9), (1, 0.2)]
+            .into_iter()
+            .map(|(step, value)| types::Metric {
+                name: "loss".to_string(),
+                value: value.into(),
+                step: Some(step.into()),
+                timestamp: None,
+            })
+            .collect();
+        let metrics = types::ListMetricResponse {
+            metrics: HashMap::from([("loss".to_string(), loss)]),
+        };
+
+        let values = metric_series(&metrics, "loss");
+        assert_eq!(values, vec![0.9, 0.2, 0.3]);
+        assert!(metric_series(&metrics, "auc").is_empty());
+
+        let stats = StepStats::from_values(&values, true).unwrap();
+        assert_eq!(stats.steps, 3);
+        assert_eq!(stats.last, 0.3);
+        assert_eq!(stats.best, 0.2);
+        assert_eq!(stats.to_string(), "0.3 / 0.2 / 0.4667");
+        assert_eq!(StepStats::from_values(&values, false).unwrap().best, 0.9);
+        assert_eq!(StepStats::from_values(&[], true), None);
+
+        let mut rng = StdRng::seed_from_u64(BOOTSTRAP_SEED);
+        let champion = [1.0, 1.1, 0.9, 1.0, 1.05, 0.95];
+        let improved = [0.5, 0.55, 0.45, 0.5, 0.52, 0.48];
+        let (lower, upper) = bootstrap_mean_delta(&champion, &improved, 500, &mut rng).unwrap();
+        assert!(lower <= upper && upper < 0.0);
+
+        let (lower, upper) = bootstrap_mean_delta(&champion, &champion, 500, &mut rng).unwrap();
+        assert!(lower < 0.0 && upper > 0.0);
+        assert_eq!(bootstrap_mean_delta(&champion, &[], 500, &mut rng), None);
+    }
+
+    #[test]
+    fn test_within_tolerance() {
+        // lower is better: 1% worse latency
+        assert!(within_tolerance(100.0, 101.0, true, 1.0));
+        assert!(!within_tolerance(100.0, 101.5, true, 1.0));
+
+        // higher is better: 1% lower auc
+        assert!(within_tolerance(0.9, 0.8911, false, 1.0));
+        assert!(!within_tolerance(0.9, 0.85, false, 1.0));
 
-        parse_compare_metric_response(&string_response);
+        // better values are always within tolerance
+        assert!(within_tolerance(0.0, -1.0, true, 0.0));
+        assert!(!within_tolerance(0.0, 1.0, true, 50.0));
     }
 }
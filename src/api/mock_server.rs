@@ -0,0 +1,586 @@
+use crate::api::logging::{self, Level};
+use crate::api::types;
+use crate::api::utils::OpsmlPaths;
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::{cmp::Ordering, fs};
+
+/// Version reported when the fixtures do not include version.json
+const MOCK_SERVER_VERSION: &str = "1.0.0";
+
+/// Response sent back to the cli
+struct Reply {
+    status: StatusCode,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Reply {
+    fn json<T: Serialize>(value: &T) -> Self {
+        Reply {
+            status: StatusCode::OK,
+            content_type: "application/json",
+            body: serde_json::to_vec(value).unwrap_or_default(),
+        }
+    }
+
+    fn error(status: StatusCode, detail: impl Into<String>) -> Self {
+        Reply {
+            status,
+            content_type: "application/json",
+            body: json!({ "detail": detail.into() }).to_string().into_bytes(),
+        }
+    }
+}
+
+/// Registry data served by the mock server
+///
+/// The fixture directory is laid out as
+///
+/// * `cards/<registry>.json` - card list response per registry (data, model, run, etc)
+/// * `metadata/<uid>.json` - model metadata per model card
+/// * `metrics/<uid>.json` - metric response per model card
+/// * `files/<path>` - artifacts, downloaded by the path in the metadata uris
+/// * `version.json` - optional version response
+pub struct Fixtures {
+    dir: PathBuf,
+}
+
+/// Registry file name for a table name, e.g. OPSML_MODEL_REGISTRY -> model
+///
+/// * `table_name` - registry table name
+fn registry_name(table_name: &str) -> String {
+    table_name
+        .trim_start_matches("OPSML_")
+        .trim_end_matches("_REGISTRY")
+        .to_lowercase()
+}
+
+/// Whether a card version matches a requested version or version prefix
+///
+/// * `version` - card version
+/// * `requested` - requested version, e.g. 1.2.0 or 1
+fn version_matches(version: &str, requested: &str) -> bool {
+    version == requested || version.starts_with(&format!("{}.", requested))
+}
+
+/// Orders card versions from newest to oldest
+fn compare_versions(a: &types::Card, b: &types::Card) -> Ordering {
+    match (
+        semver::Version::parse(&a.version),
+        semver::Version::parse(&b.version),
+    ) {
+        (Ok(a), Ok(b)) => b.cmp(&a),
+        _ => b.version.cmp(&a.version),
+    }
+}
+
+/// Resolves a download path inside the files directory
+///
+/// Storage schemes such as gs:// are dropped and paths leaving the directory are rejected.
+///
+/// * `files_dir` - artifact directory
+/// * `read_path` - requested path
+fn artifact_path(files_dir: &Path, read_path: &str) -> Option<PathBuf> {
+    let relative = match read_path.split_once("://") {
+        Some((_, path)) => path,
+        None => read_path,
+    };
+
+    let relative = Path::new(relative.trim_start_matches('/'));
+    if relative
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_)))
+    {
+        return None;
+    }
+
+    Some(files_dir.join(relative))
+}
+
+impl Fixtures {
+    /// Loads fixtures from a directory
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Fixture directory
+    pub fn load(dir: &str) -> Result<Self, String> {
+        let dir = PathBuf::from(dir);
+        if !dir.join("cards").is_dir() {
+            return Err(format!(
+                "Fixture directory {} has no cards directory",
+                dir.display()
+            ));
+        }
+
+        Ok(Fixtures { dir })
+    }
+
+    /// Reads a json fixture
+    ///
+    /// * `path` - path relative to the fixture directory
+    fn read<T: DeserializeOwned>(&self, path: &str) -> Result<T, Reply> {
+        let path = self.dir.join(path);
+        let data = fs::read_to_string(&path).map_err(|_| {
+            Reply::error(
+                StatusCode::NOT_FOUND,
+                format!("No fixture {}", path.display()),
+            )
+        })?;
+
+        serde_json::from_str(&data).map_err(|e| {
+            Reply::error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Invalid fixture {}: {}", path.display(), e),
+            )
+        })
+    }
+
+    /// Cards of a registry, newest version first
+    ///
+    /// * `table_name` - registry table name
+    fn cards(&self, table_name: &str) -> Result<Vec<types::Card>, Reply> {
+        let registry = registry_name(table_name);
+        let response: types::ListCardResponse = self.read(&format!("cards/{}.json", registry))?;
+
+        let mut cards = response.cards;
+        cards.sort_by(compare_versions);
+        Ok(cards)
+    }
+
+    /// Finds the model card a request refers to
+    ///
+    /// * `request` - card name and version, or uid
+    fn model_card(&self, request: &types::CardRequest) -> Result<types::Card, Reply> {
+        let cards = self.cards("OPSML_MODEL_REGISTRY")?;
+
+        cards
+            .into_iter()
+            .find(|card| match (&request.uid, &request.name) {
+                (Some(uid), _) => &card.uid == uid,
+                (None, Some(name)) => {
+                    &card.name == name
+                        && request
+                            .version
+                            .as_ref()
+                            .is_none_or(|version| version_matches(&card.version, version))
+                }
+                (None, None) => false,
+            })
+            .ok_or_else(|| Reply::error(StatusCode::NOT_FOUND, "Model card not found"))
+    }
+
+    /// Metrics of a model card
+    ///
+    /// * `uid` - card uid
+    fn metrics(&self, uid: &str) -> Result<types::ListMetricResponse, Reply> {
+        match self.read(&format!("metrics/{}.json", uid)) {
+            Ok(response) => Ok(response),
+            Err(reply) if reply.status == StatusCode::NOT_FOUND => Ok(types::ListMetricResponse {
+                metrics: HashMap::new(),
+            }),
+            Err(reply) => Err(reply),
+        }
+    }
+
+    fn list_cards(&self, request: types::ListTableRequest) -> Result<Reply, Reply> {
+        let tags = request.tags.unwrap_or_default();
+
+        let cards: Vec<types::Card> = self
+            .cards(&request.table_name)?
+            .into_iter()
+            .filter(|card| request.name.as_ref().is_none_or(|name| &card.name == name))
+            .filter(|card| request.team.as_ref().is_none_or(|team| &card.team == team))
+            .filter(|card| request.uid.as_ref().is_none_or(|uid| &card.uid == uid))
+            .filter(|card| {
+                request
+                    .version
+                    .as_ref()
+                    .is_none_or(|version| version_matches(&card.version, version))
+            })
+            .filter(|card| {
+                request
+                    .max_date
+                    .as_ref()
+                    .is_none_or(|max_date| &card.date <= max_date)
+            })
+            .filter(|card| tags.iter().all(|(k, v)| card.tags.get(k) == Some(v)))
+            .take(
+                request
+                    .limit
+                    .map_or(usize::MAX, |limit| limit.max(0) as usize),
+            )
+            .collect();
+
+        Ok(Reply::json(&types::ListCardResponse { cards }))
+    }
+
+    fn metadata(&self, request: types::CardRequest) -> Result<Reply, Reply> {
+        let card = self.model_card(&request)?;
+        let metadata: types::ModelMetadata = self.read(&format!("metadata/{}.json", card.uid))?;
+        Ok(Reply::json(&metadata))
+    }
+
+    fn download(&self, request: types::ModelDownloadRequest) -> Result<Reply, Reply> {
+        let path = artifact_path(&self.dir.join("files"), &request.read_path).ok_or_else(|| {
+            Reply::error(
+                StatusCode::BAD_REQUEST,
+                format!("Invalid path {}", request.read_path),
+            )
+        })?;
+
+        let body = fs::read(&path).map_err(|_| {
+            Reply::error(
+                StatusCode::NOT_FOUND,
+                format!("No file {}", request.read_path),
+            )
+        })?;
+
+        Ok(Reply {
+            status: StatusCode::OK,
+            content_type: "application/octet-stream",
+            body,
+        })
+    }
+
+    fn get_metrics(&self, request: types::CardRequest) -> Result<Reply, Reply> {
+        let card = self.model_card(&request)?;
+        Ok(Reply::json(&self.metrics(&card.uid)?))
+    }
+
+    /// Compares the last value of each metric, like the opsml server
+    fn compare_metrics(&self, request: types::CompareMetricRequest) -> Result<Reply, Reply> {
+        let by_uid = |uid: &str| types::CardRequest {
+            name: None,
+            version: None,
+            uid: Some(uid.to_string()),
+        };
+
+        let challenger = self.model_card(&by_uid(&request.challenger_uid))?;
+        let challenger_metrics = self.metrics(&challenger.uid)?;
+
+        let mut champions = Vec::new();
+        for uid in request.champion_uid.iter() {
+            let champion = self.model_card(&by_uid(uid))?;
+            let metrics = self.metrics(uid)?;
+            champions.push((champion, metrics));
+        }
+
+        let last = |metrics: &types::ListMetricResponse, name: &str| {
+            metrics
+                .metrics
+                .get(name)
+                .and_then(|values| values.last().cloned())
+        };
+
+        let mut report = HashMap::new();
+        for (i, name) in request.metric_name.iter().enumerate() {
+            let lower_is_better = request.lower_is_better.get(i).copied().unwrap_or(true);
+            let challenger_metric = last(&challenger_metrics, name);
+
+            let battles = champions
+                .iter()
+                .map(|(champion, metrics)| {
+                    let champion_metric = last(metrics, name);
+                    let values = (
+                        challenger_metric.as_ref().and_then(|m| m.value.as_f64()),
+                        champion_metric.as_ref().and_then(|m| m.value.as_f64()),
+                    );
+                    let challenger_win = match values {
+                        (Some(challenger), Some(champion)) if lower_is_better => {
+                            challenger < champion
+                        }
+                        (Some(challenger), Some(champion)) => challenger > champion,
+                        _ => false,
+                    };
+
+                    types::BattleReport {
+                        champion_name: champion.name.clone(),
+                        champion_version: champion.version.clone(),
+                        champion_metric,
+                        challenger_metric: challenger_metric.clone(),
+                        challenger_win,
+                    }
+                })
+                .collect();
+
+            report.insert(name.clone(), battles);
+        }
+
+        Ok(Reply::json(&types::CompareMetricResponse {
+            challenger_name: challenger.name,
+            challenger_version: challenger.version,
+            report,
+        }))
+    }
+
+    /// Routes a request to its handler
+    ///
+    /// * `method` - http method
+    /// * `path` - request path
+    /// * `body` - request body
+    fn handle(&self, method: &str, path: &str, body: &[u8]) -> Reply {
+        fn parse<T: DeserializeOwned>(body: &[u8]) -> Result<T, Reply> {
+            serde_json::from_slice(body).map_err(|e| {
+                Reply::error(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    format!("Invalid request: {}", e),
+                )
+            })
+        }
+
+        let route = |path: OpsmlPaths| path.url("");
+
+        let result = match method {
+            "GET" if path == route(OpsmlPaths::HealthCheck) => {
+                Ok(Reply::json(&json!({ "is_alive": true })))
+            }
+            "GET" if path == route(OpsmlPaths::Version) => {
+                match self.read::<serde_json::Value>("version.json") {
+                    Err(reply) if reply.status == StatusCode::NOT_FOUND => {
+                        Ok(Reply::json(&json!({ "version": MOCK_SERVER_VERSION })))
+                    }
+                    result => result.map(|version| Reply::json(&version)),
+                }
+            }
+            "POST" if path == route(OpsmlPaths::ListCard) => {
+                parse(body).and_then(|r| self.list_cards(r))
+            }
+            "POST" if path == route(OpsmlPaths::MetadataDownload) => {
+                parse(body).and_then(|r| self.metadata(r))
+            }
+            "POST" if path == route(OpsmlPaths::Download) => {
+                parse(body).and_then(|r| self.download(r))
+            }
+            "POST" if path == route(OpsmlPaths::Metric) => {
+                parse(body).and_then(|r| self.get_metrics(r))
+            }
+            "POST" if path == route(OpsmlPaths::CompareMetric) => {
+                parse(body).and_then(|r| self.compare_metrics(r))
+            }
+            _ => Err(Reply::error(StatusCode::NOT_FOUND, "Not Found")),
+        };
+
+        result.unwrap_or_else(|reply| reply)
+    }
+}
+
+/// Reads one http request and writes the fixture response
+///
+/// * `fixtures` - registry data
+/// * `stream` - client connection
+fn handle_connection(fixtures: &Fixtures, stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    let reply = fixtures.handle(&method, &path, &body);
+    logging::log(
+        Level::Info,
+        "mock request",
+        &[
+            ("method", method.into()),
+            ("path", path.into()),
+            ("status", reply.status.as_u16().into()),
+        ],
+    );
+
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        reply.status.as_u16(),
+        reply.status.canonical_reason().unwrap_or_default(),
+        reply.content_type,
+        reply.body.len()
+    )?;
+    stream.write_all(&reply.body)?;
+    stream.flush()
+}
+
+/// Starts serving fixtures in a background thread
+///
+/// Returns the bound address, which has the assigned port when `addr` uses port 0.
+///
+/// # Arguments
+///
+/// * `fixtures` - Fixture directory
+/// * `addr` - Address to listen on
+pub fn start(fixtures: &str, addr: &str) -> Result<(SocketAddr, JoinHandle<()>), String> {
+    let fixtures = Arc::new(Fixtures::load(fixtures)?);
+    let listener =
+        TcpListener::bind(addr).map_err(|e| format!("Failed to listen on {}: {}", addr, e))?;
+    let local_addr = listener.local_addr().map_err(|e| e.to_string())?;
+
+    let handle = thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let fixtures = Arc::clone(&fixtures);
+            thread::spawn(move || {
+                if let Err(e) = handle_connection(&fixtures, stream) {
+                    logging::log(
+                        Level::Warn,
+                        "mock request failed",
+                        &[("error", e.to_string().into())],
+                    );
+                }
+            });
+        }
+    });
+
+    Ok((local_addr, handle))
+}
+
+/// Serves an opsml api from a directory of fixtures until interrupted
+///
+/// # Arguments
+///
+/// * `fixtures` - Fixture directory
+/// * `host` - Host to listen on
+/// * `port` - Port to listen on, 0 picks a free port
+pub fn mock_server(fixtures: &str, host: &str, port: u16) -> Result<(), String> {
+    let (addr, handle) = start(fixtures, &format!("{}:{}", host, port))?;
+
+    println!("Serving fixtures from {} on http://{}", fixtures, addr);
+    println!("export OPSML_TRACKING_URI=http://{}", addr);
+
+    handle
+        .join()
+        .map_err(|_| "Mock server stopped unexpectedly".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::utils::make_post_request;
+    use reqwest::Response;
+
+    const FIXTURES: &str = "./src/api/test_utils/fixtures";
+
+    async fn post<T: Serialize>(base: &str, path: OpsmlPaths, body: &T) -> Response {
+        make_post_request(&path.url(base), body).await.unwrap()
+    }
+
+    #[test]
+    fn test_artifact_path() {
+        let files = Path::new("files");
+        assert_eq!(
+            artifact_path(files, "gs://bucket/linear-reg1/model.onnx"),
+            Some(files.join("bucket/linear-reg1/model.onnx"))
+        );
+        assert_eq!(artifact_path(files, "bucket/../../secret"), None);
+        assert!(version_matches("1.1.0", "1"));
+        assert!(!version_matches("1.10.0", "1.1"));
+    }
+
+    #[tokio::test]
+    async fn test_mock_server() {
+        let (addr, _) = start(FIXTURES, "127.0.0.1:0").unwrap();
+        let base = format!("http://{}", addr);
+
+        let list_request = types::ListTableRequest {
+            table_name: "OPSML_MODEL_REGISTRY".to_string(),
+            name: Some("linear-reg1".to_string()),
+            team: None,
+            version: Some("1".to_string()),
+            uid: None,
+            limit: None,
+            tags: None,
+            max_date: None,
+        };
+        let cards: types::ListCardResponse = post(&base, OpsmlPaths::ListCard, &list_request)
+            .await
+            .json()
+            .await
+            .unwrap();
+        let versions: Vec<&str> = cards.cards.iter().map(|c| c.version.as_str()).collect();
+        assert_eq!(versions, vec!["1.1.0", "1.0.0"]);
+
+        let card_request = types::CardRequest {
+            name: Some("linear-reg1".to_string()),
+            version: None,
+            uid: None,
+        };
+        let metadata: types::ModelMetadata =
+            post(&base, OpsmlPaths::MetadataDownload, &card_request)
+                .await
+                .json()
+                .await
+                .unwrap();
+        assert_eq!(metadata.model_version, "1.1.0");
+
+        let download_request = types::ModelDownloadRequest {
+            read_path: metadata.onnx_uri.unwrap(),
+        };
+        let artifact = post(&base, OpsmlPaths::Download, &download_request)
+            .await
+            .bytes()
+            .await
+            .unwrap();
+        assert_eq!(
+            artifact.to_vec(),
+            fs::read("./src/api/test_utils/model.onnx").unwrap()
+        );
+
+        let compare_request = types::CompareMetricRequest {
+            metric_name: vec!["mae".to_string(), "r2".to_string()],
+            lower_is_better: vec![true, false],
+            challenger_uid: cards.cards[0].uid.clone(),
+            champion_uid: vec![cards.cards[1].uid.clone()],
+        };
+        let compare: types::CompareMetricResponse =
+            post(&base, OpsmlPaths::CompareMetric, &compare_request)
+                .await
+                .json()
+                .await
+                .unwrap();
+        assert!(compare.report["mae"][0].challenger_win);
+        assert!(!compare.report["r2"][0].challenger_win);
+
+        let metrics: types::ListMetricResponse = post(&base, OpsmlPaths::Metric, &card_request)
+            .await
+            .json()
+            .await
+            .unwrap();
+        assert!(metrics.metrics.contains_key("mae"));
+
+        let missing = types::CardRequest {
+            name: None,
+            version: None,
+            uid: Some("missing".to_string()),
+        };
+        assert_eq!(
+            post(&base, OpsmlPaths::MetadataDownload, &missing)
+                .await
+                .status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+}
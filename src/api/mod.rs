@@ -1,6 +1,23 @@
+pub mod cassette;
 pub mod command_structs;
+pub mod compare_report;
+pub mod compare_spec;
+pub mod completions;
+pub mod diff_metrics;
+pub mod doctor;
 pub mod download_file;
+pub mod inspect_onnx;
+pub mod leaderboard;
 pub mod list_cards;
+pub mod lockfile;
+pub mod logging;
 pub mod metrics;
+pub mod mock_server;
+pub mod output;
+pub mod plot_metrics;
+pub mod predict;
+pub mod resolve;
+pub mod server;
 pub mod types;
 pub mod utils;
+pub mod watch_model;
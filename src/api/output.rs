@@ -0,0 +1,68 @@
+use clap::ValueEnum;
+use tabled::settings::style::Style;
+use tabled::{settings::Alignment, Table};
+
+/// Format command results are printed in
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable table
+    #[default]
+    Table,
+    /// Json array of records
+    Json,
+    /// Csv with a header row
+    Csv,
+}
+
+/// Renders records as a table
+///
+/// * `header` - column names
+/// * `records` - rows of cell values
+pub fn render_table(header: &[&str], records: &[Vec<String>]) -> String {
+    let mut builder = tabled::builder::Builder::default();
+    builder.set_header(header.to_vec());
+
+    for record in records.iter() {
+        builder.push_record(record.clone());
+    }
+
+    let mut table: Table = builder.build();
+    table
+        .with(Alignment::center())
+        .with(Style::sharp())
+        .to_string()
+}
+
+/// Renders records as csv with a header row
+///
+/// * `header` - column names
+/// * `records` - rows of cell values
+pub fn render_csv(header: &[&str], records: &[Vec<String>]) -> Result<String, String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+
+    writer.write_record(header).map_err(|e| e.to_string())?;
+    for record in records.iter() {
+        writer.write_record(record).map_err(|e| e.to_string())?;
+    }
+
+    let data = writer.into_inner().map_err(|e| e.to_string())?;
+    String::from_utf8(data).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_csv() {
+        let records = vec![
+            vec!["model".to_string(), "0.9".to_string()],
+            vec!["model, v2".to_string(), "0.8".to_string()],
+        ];
+
+        assert_eq!(
+            render_csv(&["name", "auc"], &records).unwrap(),
+            "name,auc\nmodel,0.9\n\"model, v2\",0.8\n"
+        );
+    }
+}
@@ -0,0 +1,342 @@
+use crate::api::metrics::{fetch_model_metrics, metric_points};
+use crate::api::resolve::{resolve_cards, ModelSelector};
+use crate::api::types;
+use crate::api::utils;
+use plotters::prelude::*;
+use resvg::{tiny_skia, usvg};
+use std::fs;
+use std::path::Path;
+
+/// Chart size in pixels
+const CHART_SIZE: (u32, u32) = (1024, 640);
+
+/// Metric values logged by a single model
+struct ModelSeries {
+    label: String,
+    points: Vec<(f64, f64)>,
+}
+
+/// Expands a value range so flat series still get a visible axis
+///
+/// * `values` - values plotted on the axis
+/// * `include_zero` - whether the axis must start at zero
+fn axis_range(values: impl Iterator<Item = f64>, include_zero: bool) -> (f64, f64) {
+    let (mut min, mut max) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
+        (min.min(v), max.max(v))
+    });
+
+    if include_zero {
+        min = min.min(0.0);
+        max = max.max(0.0);
+    }
+
+    if min == max {
+        let pad = if min == 0.0 { 1.0 } else { min.abs() * 0.1 };
+        return (min - pad, max + pad);
+    }
+
+    let pad = (max - min) * 0.05;
+    (
+        if include_zero && min == 0.0 {
+            0.0
+        } else {
+            min - pad
+        },
+        max + pad,
+    )
+}
+
+/// Draws one line per model over the metric steps
+///
+/// * `metric` - metric name
+/// * `series` - metric values per model
+fn render_line_chart(metric: &str, series: &[ModelSeries]) -> Result<String, String> {
+    let (x_min, x_max) = axis_range(
+        series.iter().flat_map(|s| s.points.iter().map(|p| p.0)),
+        false,
+    );
+    let (y_min, y_max) = axis_range(
+        series.iter().flat_map(|s| s.points.iter().map(|p| p.1)),
+        false,
+    );
+
+    let mut svg = String::new();
+    {
+        let root = SVGBackend::with_string(&mut svg, CHART_SIZE).into_drawing_area();
+        root.fill(&WHITE).map_err(|e| e.to_string())?;
+
+        let mut chart = ChartBuilder::on(&root)
+            .caption(metric, ("sans-serif", 24))
+            .margin(20)
+            .x_label_area_size(40)
+            .y_label_area_size(70)
+            .build_cartesian_2d(x_min..x_max, y_min..y_max)
+            .map_err(|e| e.to_string())?;
+
+        chart
+            .configure_mesh()
+            .x_desc("step")
+            .y_desc(metric)
+            .draw()
+            .map_err(|e| e.to_string())?;
+
+        for (i, model) in series.iter().enumerate() {
+            let color = Palette99::pick(i).mix(1.0);
+
+            chart
+                .draw_series(LineSeries::new(
+                    model.points.iter().copied(),
+                    color.stroke_width(2),
+                ))
+                .map_err(|e| e.to_string())?
+                .label(model.label.clone())
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+
+            // a single step has no line to draw
+            if model.points.len() == 1 {
+                chart
+                    .draw_series(
+                        model
+                            .points
+                            .iter()
+                            .map(|point| Circle::new(*point, 4, color.filled())),
+                    )
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+
+        chart
+            .configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()
+            .map_err(|e| e.to_string())?;
+
+        root.present().map_err(|e| e.to_string())?;
+    }
+
+    Ok(svg)
+}
+
+/// Draws one bar per model for a scalar metric
+///
+/// * `metric` - metric name
+/// * `series` - metric value per model
+fn render_bar_chart(metric: &str, series: &[ModelSeries]) -> Result<String, String> {
+    let values: Vec<f64> = series
+        .iter()
+        .map(|model| model.points.last().map_or(0.0, |point| point.1))
+        .collect();
+    let (y_min, y_max) = axis_range(values.iter().copied(), true);
+
+    let mut svg = String::new();
+    {
+        let root = SVGBackend::with_string(&mut svg, CHART_SIZE).into_drawing_area();
+        root.fill(&WHITE).map_err(|e| e.to_string())?;
+
+        let mut chart = ChartBuilder::on(&root)
+            .caption(metric, ("sans-serif", 24))
+            .margin(20)
+            .x_label_area_size(40)
+            .y_label_area_size(70)
+            .build_cartesian_2d((0..series.len() - 1).into_segmented(), y_min..y_max)
+            .map_err(|e| e.to_string())?;
+
+        chart
+            .configure_mesh()
+            .disable_x_mesh()
+            .y_desc(metric)
+            .x_labels(series.len())
+            .x_label_formatter(&|segment| match segment {
+                SegmentValue::CenterOf(i) => series
+                    .get(*i)
+                    .map(|model| model.label.clone())
+                    .unwrap_or_default(),
+                _ => String::new(),
+            })
+            .draw()
+            .map_err(|e| e.to_string())?;
+
+        chart
+            .draw_series(
+                Histogram::vertical(&chart)
+                    .style_func(|segment, _| match segment {
+                        SegmentValue::CenterOf(i) | SegmentValue::Exact(i) => {
+                            Palette99::pick(*i).filled()
+                        }
+                        SegmentValue::Last => Palette99::pick(0).filled(),
+                    })
+                    .margin(20)
+                    .data(values.iter().enumerate().map(|(i, value)| (i, *value))),
+            )
+            .map_err(|e| e.to_string())?;
+
+        root.present().map_err(|e| e.to_string())?;
+    }
+
+    Ok(svg)
+}
+
+/// Renders a metric chart, a line chart for step series and a bar chart for scalars
+///
+/// * `metric` - metric name
+/// * `series` - metric values per model
+fn render_chart(metric: &str, series: &[ModelSeries]) -> Result<String, String> {
+    if series.iter().any(|model| model.points.len() > 1) {
+        render_line_chart(metric, series)
+    } else {
+        render_bar_chart(metric, series)
+    }
+}
+
+/// Rasterises an svg chart to a png file
+///
+/// * `svg` - svg document
+/// * `path` - png file path
+fn write_png(svg: &str, path: &str) -> Result<(), String> {
+    let mut options = usvg::Options::default();
+    let fontdb = options.fontdb_mut();
+    fontdb.load_system_fonts();
+
+    // charts use the generic sans-serif family, which defaults to a font most
+    // linux systems do not have
+    let sans_serif = usvg::fontdb::Query {
+        families: &[usvg::fontdb::Family::SansSerif],
+        ..Default::default()
+    };
+    if fontdb.query(&sans_serif).is_none() {
+        let families: Vec<String> = fontdb
+            .faces()
+            .filter_map(|face| face.families.first().map(|family| family.0.clone()))
+            .collect();
+        let fallback = families
+            .iter()
+            .find(|family| family.contains("Sans") && !family.contains("Mono"))
+            .or(families.first())
+            .cloned();
+        if let Some(family) = fallback {
+            fontdb.set_sans_serif_family(family);
+        }
+    }
+
+    let tree = usvg::Tree::from_str(svg, &options).map_err(|e| e.to_string())?;
+    let size = tree.size().to_int_size();
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height())
+        .ok_or("Failed to allocate chart image")?;
+
+    resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+    pixmap
+        .save_png(path)
+        .map_err(|e| format!("Failed to write {}: {}", path, e))
+}
+
+/// Plots a metric for one or more models to an svg or png file
+///
+/// # Arguments
+///
+/// * `uids` - Model uids
+/// * `metric` - Metric name
+/// * `out` - Output file, ending in .svg or .png
+#[tokio::main]
+pub async fn plot_metrics(
+    uids: &[String],
+    metric: &str,
+    out: &str,
+) -> Result<(), utils::RequestError> {
+    let extension = Path::new(out)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+
+    if extension != "svg" && extension != "png" {
+        return Err(format!("Unsupported chart file {}, expected .svg or .png", out).into());
+    }
+
+    let mut series = Vec::new();
+    for uid in uids.iter() {
+        let card = resolve_cards(&ModelSelector::Uid(uid.clone()))
+            .await?
+            .remove(0);
+        let metrics = fetch_model_metrics(&types::CardRequest {
+            name: None,
+            version: None,
+            uid: Some(uid.clone()),
+        })
+        .await?;
+
+        let points: Vec<(f64, f64)> = metric_points(&metrics, metric)
+            .into_iter()
+            .enumerate()
+            .map(|(i, (step, value))| (step.unwrap_or(i as f64), value))
+            .collect();
+
+        if points.is_empty() {
+            eprintln!("{} {} did not log {}", card.name, card.version, metric);
+            continue;
+        }
+
+        series.push(ModelSeries {
+            label: format!("{} {}", card.name, card.version),
+            points,
+        });
+    }
+
+    if series.is_empty() {
+        return Err(format!("No model logged {}", metric).into());
+    }
+
+    let svg = render_chart(metric, &series)?;
+
+    match extension.as_str() {
+        "png" => write_png(&svg, out)?,
+        _ => fs::write(out, &svg).map_err(|e| format!("Failed to write {}: {}", out, e))?,
+    }
+
+    println!("Wrote {} chart to {}", metric, out);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(points: &[&[(f64, f64)]]) -> Vec<ModelSeries> {
+        points
+            .iter()
+            .enumerate()
+            .map(|(i, points)| ModelSeries {
+                label: format!("model 1.{}.0", i),
+                points: points.to_vec(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_axis_range() {
+        assert_eq!(axis_range([0.0, 20.0].into_iter(), false), (-1.0, 21.0));
+        assert_eq!(axis_range([10.0].into_iter(), false), (9.0, 11.0));
+        assert_eq!(axis_range([0.0].into_iter(), true), (-1.0, 1.0));
+        assert_eq!(axis_range([20.0, 40.0].into_iter(), true), (0.0, 42.0));
+    }
+
+    #[test]
+    fn test_render_chart() {
+        let line =
+            render_chart("loss", &series(&[&[(0.0, 1.0), (1.0, 0.5)], &[(0.0, 0.9)]])).unwrap();
+        assert!(line.starts_with("<svg"));
+        assert!(line.contains("model 1.0.0"));
+        assert!(line.contains("\nstep\n"));
+
+        let bar = render_chart("auc", &series(&[&[(0.0, 0.8)], &[(0.0, 0.9)]])).unwrap();
+        assert!(bar.contains("model 1.1.0"));
+        assert!(!bar.contains("\nstep\n"));
+
+        let path = std::env::temp_dir().join("plot_metrics_chart.png");
+        write_png(&bar, path.to_str().unwrap()).unwrap();
+        let png = fs::read(&path).unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+        fs::remove_file(&path).unwrap();
+    }
+}
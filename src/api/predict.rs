@@ -0,0 +1,443 @@
+use crate::api::download_file::load_model_metadata;
+use crate::api::types;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::{fs, path::Path};
+use tabled::settings::style::Style;
+use tabled::settings::Alignment;
+use tract_onnx::prelude::*;
+use tract_onnx::tract_hir::infer::Factoid;
+
+/// Gets the local path of the downloaded onnx model
+///
+/// * `write_dir` - directory the model was downloaded to
+/// * `model_metadata` - metadata of the downloaded model
+fn get_onnx_path(write_dir: &str, model_metadata: &types::ModelMetadata) -> Result<String, String> {
+    let onnx_uri = model_metadata
+        .onnx_uri
+        .as_ref()
+        .ok_or("No onnx model uri found in model metadata")?;
+
+    let filename = Path::new(onnx_uri)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or(format!("Invalid onnx model uri {}", onnx_uri))?;
+
+    let onnx_path = format!("{}/{}", write_dir, filename);
+    if !Path::new(&onnx_path).exists() {
+        return Err(format!(
+            "Onnx model {} not found. Run download-model first",
+            onnx_path
+        ));
+    }
+
+    Ok(onnx_path)
+}
+
+/// Loads the data to feed the model with (payload file or metadata sample data)
+///
+/// * `payload` - Optional path to a json file mapping input names to values
+/// * `model_metadata` - metadata of the downloaded model
+fn load_payload(
+    payload: Option<&str>,
+    model_metadata: &types::ModelMetadata,
+) -> Result<HashMap<String, Value>, String> {
+    match payload {
+        Some(path) => {
+            let data = fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read payload {}: {}", path, e))?;
+            serde_json::from_str(&data).map_err(|e| format!("Failed to parse payload: {}", e))
+        }
+        None => Ok(model_metadata.sample_data.clone()),
+    }
+}
+
+/// Maps a data schema feature type to an onnx datum type
+///
+/// * `feature_type` - feature type recorded in the data schema (e.g. float32, int64)
+fn get_datum_type(feature_type: &str) -> Result<DatumType, String> {
+    match feature_type.to_lowercase().as_str() {
+        "float32" | "float" => Ok(DatumType::F32),
+        "float64" | "double" => Ok(DatumType::F64),
+        "int32" => Ok(DatumType::I32),
+        "int64" | "int" => Ok(DatumType::I64),
+        "bool" => Ok(DatumType::Bool),
+        "str" | "string" | "object" => Ok(DatumType::String),
+        _ => Err(format!("Unsupported feature type {}", feature_type)),
+    }
+}
+
+/// Flattens a (possibly nested) json value into a list of scalar values
+///
+/// * `value` - json value to flatten
+/// * `values` - buffer to push scalar values into
+fn flatten_value(value: &Value, values: &mut Vec<Value>) {
+    match value {
+        Value::Array(items) => items.iter().for_each(|item| flatten_value(item, values)),
+        _ => values.push(value.clone()),
+    }
+}
+
+/// Resolves the tensor shape for a feature
+///
+/// Unknown dimensions (null or negative) are inferred from the number of values.
+/// If the schema shape has no unknown dimension, a batch dimension of 1 is prepended.
+///
+/// * `feature_shape` - shape recorded in the data schema
+/// * `num_values` - number of scalar values provided for the feature
+fn resolve_shape(feature_shape: &[Option<i64>], num_values: usize) -> Result<Vec<usize>, String> {
+    let known: usize = feature_shape
+        .iter()
+        .filter_map(|dim| dim.filter(|d| *d >= 0))
+        .product::<i64>() as usize;
+
+    let has_unknown = feature_shape.iter().any(|dim| dim.is_none_or(|d| d < 0));
+
+    if known == 0 || !num_values.is_multiple_of(known) {
+        return Err(format!(
+            "Cannot shape {} values into {:?}",
+            num_values, feature_shape
+        ));
+    }
+
+    let shape: Vec<usize> = if has_unknown {
+        let mut inferred = false;
+        feature_shape
+            .iter()
+            .map(|dim| match dim {
+                Some(d) if *d >= 0 => *d as usize,
+                _ if !inferred => {
+                    inferred = true;
+                    num_values / known
+                }
+                _ => 1,
+            })
+            .collect()
+    } else {
+        if num_values != known {
+            return Err(format!(
+                "Expected {} values for shape {:?}, got {}",
+                known, feature_shape, num_values
+            ));
+        }
+        std::iter::once(1)
+            .chain(feature_shape.iter().map(|dim| dim.unwrap_or(1) as usize))
+            .collect()
+    };
+
+    Ok(shape)
+}
+
+/// Reads the feature type and shape of an input from the data schema
+///
+/// * `feature` - feature entry of `input_features`
+fn parse_feature(feature: &Value) -> (Option<String>, Vec<Option<i64>>) {
+    let feature_type = feature
+        .get("feature_type")
+        .and_then(|t| t.as_str())
+        .map(|t| t.to_string());
+
+    let shape = match feature.get("shape").and_then(|s| s.as_array()) {
+        Some(dims) => dims.iter().map(|d| d.as_i64()).collect(),
+        None => vec![None],
+    };
+
+    (feature_type, shape)
+}
+
+/// Converts json values into a tensor of the given type and shape
+///
+/// * `values` - flattened scalar values
+/// * `datum_type` - tensor datum type
+/// * `shape` - tensor shape
+fn values_to_tensor(
+    values: &[Value],
+    datum_type: DatumType,
+    shape: &[usize],
+) -> Result<Tensor, String> {
+    let invalid = |v: &Value| format!("Value {} is not a valid {:?}", v, datum_type);
+
+    let tensor = match datum_type {
+        DatumType::String => {
+            let data: Vec<String> = values
+                .iter()
+                .map(|v| match v {
+                    Value::String(s) => s.clone(),
+                    _ => v.to_string(),
+                })
+                .collect();
+            tract_ndarray::ArrayD::from_shape_vec(shape, data)
+                .map_err(|e| e.to_string())?
+                .into_tensor()
+        }
+        DatumType::Bool => {
+            let data = values
+                .iter()
+                .map(|v| v.as_bool().ok_or_else(|| invalid(v)))
+                .collect::<Result<Vec<bool>, String>>()?;
+            Tensor::from_shape(shape, &data).map_err(|e| e.to_string())?
+        }
+        DatumType::I32 | DatumType::I64 => {
+            let data = values
+                .iter()
+                .map(|v| {
+                    // integers may be serialized as floats (e.g. 3.0)
+                    v.as_i64()
+                        .or_else(|| v.as_f64().filter(|f| f.fract() == 0.0).map(|f| f as i64))
+                        .ok_or_else(|| invalid(v))
+                })
+                .collect::<Result<Vec<i64>, String>>()?;
+            Tensor::from_shape(shape, &data)
+                .and_then(|t| t.cast_to_dt(datum_type).map(|t| t.into_owned()))
+                .map_err(|e| e.to_string())?
+        }
+        _ => {
+            let data = values
+                .iter()
+                .map(|v| v.as_f64().ok_or_else(|| invalid(v)))
+                .collect::<Result<Vec<f64>, String>>()?;
+            Tensor::from_shape(shape, &data)
+                .and_then(|t| t.cast_to_dt(datum_type).map(|t| t.into_owned()))
+                .map_err(|e| e.to_string())?
+        }
+    };
+
+    Ok(tensor)
+}
+
+/// Renders tensor values as a json string
+///
+/// * `tensor` - tensor to render
+fn tensor_to_string(tensor: &Tensor) -> Result<String, String> {
+    let datum_type = tensor.datum_type();
+
+    let values: Vec<Value> = if datum_type == DatumType::String {
+        let view = tensor
+            .to_plain_array_view::<String>()
+            .map_err(|e| e.to_string())?;
+        view.iter().map(|v| Value::from(v.clone())).collect()
+    } else if datum_type == DatumType::Bool {
+        let view = tensor
+            .to_plain_array_view::<bool>()
+            .map_err(|e| e.to_string())?;
+        view.iter().map(|v| Value::from(*v)).collect()
+    } else if datum_type.is_integer() {
+        let cast = tensor.cast_to::<i64>().map_err(|e| e.to_string())?;
+        let view = cast
+            .to_plain_array_view::<i64>()
+            .map_err(|e| e.to_string())?;
+        view.iter().map(|v| Value::from(*v)).collect()
+    } else {
+        let cast = tensor.cast_to::<f64>().map_err(|e| e.to_string())?;
+        let view = cast
+            .to_plain_array_view::<f64>()
+            .map_err(|e| e.to_string())?;
+        view.iter().map(|v| Value::from(*v)).collect()
+    };
+
+    Ok(Value::from(values).to_string())
+}
+
+/// Builds the input tensors for the model
+///
+/// * `model` - onnx model
+/// * `model_metadata` - metadata of the downloaded model
+/// * `data` - input values keyed by input name
+fn build_inputs(
+    model: &InferenceModel,
+    model_metadata: &types::ModelMetadata,
+    data: &HashMap<String, Value>,
+) -> Result<Vec<Tensor>, String> {
+    let data_schema = &model_metadata.data_schema;
+    let input_outlets = model.input_outlets().map_err(|e| e.to_string())?;
+    let mut inputs = Vec::new();
+
+    for (ix, outlet) in input_outlets.iter().enumerate() {
+        let input_name = &model.node(outlet.node).name;
+
+        // fall back to the only provided value for single input models
+        let value = match data.get(input_name) {
+            Some(value) => value,
+            None if input_outlets.len() == 1 && data.len() == 1 => data.values().next().unwrap(),
+            None => return Err(format!("No value provided for model input {}", input_name)),
+        };
+
+        // onnx input schema takes precedence over the model data schema
+        let feature = data_schema
+            .input_data_schema
+            .as_ref()
+            .and_then(|schema| schema.get(input_name))
+            .or_else(|| data_schema.model_data_schema.input_features.get(input_name));
+
+        let (feature_type, feature_shape) = match feature {
+            Some(feature) => parse_feature(feature),
+            None => (None, vec![None]),
+        };
+
+        let datum_type = match feature_type {
+            Some(feature_type) => get_datum_type(&feature_type)?,
+            None => model
+                .input_fact(ix)
+                .map_err(|e| e.to_string())?
+                .datum_type
+                .concretize()
+                .unwrap_or(DatumType::F32),
+        };
+
+        let mut values = Vec::new();
+        flatten_value(value, &mut values);
+        let shape = resolve_shape(&feature_shape, values.len())
+            .map_err(|e| format!("Input {}: {}", input_name, e))?;
+
+        inputs.push(values_to_tensor(&values, datum_type, &shape)?);
+    }
+
+    Ok(inputs)
+}
+
+/// Runs an onnx model and returns a table of its outputs
+///
+/// * `onnx_path` - path to the onnx model
+/// * `model_metadata` - metadata of the downloaded model
+/// * `data` - input values keyed by input name
+fn run_onnx_model(
+    onnx_path: &str,
+    model_metadata: &types::ModelMetadata,
+    data: &HashMap<String, Value>,
+) -> Result<String, String> {
+    let mut model = tract_onnx::onnx()
+        .model_for_path(onnx_path)
+        .map_err(|e| format!("Failed to load onnx model: {}", e))?;
+
+    let inputs = build_inputs(&model, model_metadata, data)?;
+    for (ix, input) in inputs.iter().enumerate() {
+        model
+            .set_input_fact(ix, InferenceFact::dt_shape_from_tensor(input))
+            .map_err(|e| e.to_string())?;
+    }
+
+    let output_names: Vec<String> = model
+        .output_outlets()
+        .map_err(|e| e.to_string())?
+        .iter()
+        .map(|outlet| match model.outlet_label(*outlet) {
+            Some(label) => label.to_string(),
+            None => model.node(outlet.node).name.clone(),
+        })
+        .collect();
+
+    let runnable = model
+        .into_optimized()
+        .and_then(|m| m.into_runnable())
+        .map_err(|e| format!("Failed to prepare onnx model: {}", e))?;
+
+    let outputs = runnable
+        .run(inputs.into_iter().map(|t| t.into()).collect())
+        .map_err(|e| format!("Failed to run onnx model: {}", e))?;
+
+    let mut builder = tabled::builder::Builder::default();
+    builder.set_header(vec!["Output", "Type", "Shape", "Values"]);
+
+    for (name, output) in output_names.iter().zip(outputs.iter()) {
+        builder.push_record(vec![
+            name.clone(),
+            format!("{:?}", output.datum_type()),
+            format!("{:?}", output.shape()),
+            tensor_to_string(output)?,
+        ]);
+    }
+
+    let output_table = builder
+        .build()
+        .with(Alignment::center())
+        .with(Style::sharp())
+        .to_string();
+
+    Ok(output_table)
+}
+
+/// Runs a downloaded onnx model against sample data or a payload
+///
+/// # Arguments
+///
+/// * `write_dir` - Directory the model was downloaded to
+/// * `payload` - Optional path to a json file mapping input names to values
+pub fn predict(write_dir: &str, payload: Option<&str>) -> Result<(), String> {
+    let model_metadata = load_model_metadata(write_dir)?;
+    let onnx_path = get_onnx_path(write_dir, &model_metadata)?;
+    let data = load_payload(payload, &model_metadata)?;
+
+    let output_table = run_onnx_model(&onnx_path, &model_metadata, &data)?;
+    println!("{}", output_table);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_shape() {
+        // known shape gets a batch dimension
+        assert_eq!(resolve_shape(&[Some(1)], 1).unwrap(), vec![1, 1]);
+        assert_eq!(resolve_shape(&[Some(4)], 4).unwrap(), vec![1, 4]);
+
+        // unknown dimension is inferred
+        assert_eq!(resolve_shape(&[None, Some(2)], 6).unwrap(), vec![3, 2]);
+        assert_eq!(resolve_shape(&[Some(-1), Some(3)], 3).unwrap(), vec![1, 3]);
+
+        // mismatched values
+        assert!(resolve_shape(&[Some(4)], 3).is_err());
+        assert!(resolve_shape(&[None, Some(2)], 3).is_err());
+    }
+
+    #[test]
+    fn test_build_input_tensor() {
+        let path = "./src/api/test_utils/metadata_onnx.json";
+        let data = fs::read_to_string(path).expect("Unable to read file");
+        let model_metadata: types::ModelMetadata = serde_json::from_str(&data).unwrap();
+        let input_features = &model_metadata.data_schema.model_data_schema.input_features;
+
+        let (feature_type, feature_shape) = parse_feature(&input_features["col_11"]);
+        assert_eq!(feature_type.as_deref(), Some("int64"));
+
+        let datum_type = get_datum_type(&feature_type.unwrap()).unwrap();
+        let mut values = Vec::new();
+        flatten_value(&model_metadata.sample_data["col_11"], &mut values);
+        let shape = resolve_shape(&feature_shape, values.len()).unwrap();
+
+        // sample data stores integers as floats
+        let tensor = values_to_tensor(&values, datum_type, &shape).unwrap();
+        assert_eq!(tensor.datum_type(), DatumType::I64);
+        assert_eq!(tensor.shape(), &[1, 1]);
+        assert_eq!(tensor_to_string(&tensor).unwrap(), "[3]");
+
+        let invalid = vec![Value::from("a")];
+        assert!(values_to_tensor(&invalid, DatumType::F32, &[1, 1]).is_err());
+    }
+
+    #[test]
+    fn test_run_onnx_model() {
+        let path = "./src/api/test_utils/metadata_onnx.json";
+        let data = fs::read_to_string(path).expect("Unable to read file");
+        let model_metadata: types::ModelMetadata = serde_json::from_str(&data).unwrap();
+
+        // linear model over col_0..col_9 with float inputs from input_data_schema
+        let onnx_path = "./src/api/test_utils/model.onnx";
+        let output_table =
+            run_onnx_model(onnx_path, &model_metadata, &model_metadata.sample_data).unwrap();
+        assert!(output_table.contains("variable"));
+        assert!(output_table.contains("F32"));
+        assert!(output_table.contains("[1, 1]"));
+
+        // missing inputs are reported
+        let mut payload = model_metadata.sample_data.clone();
+        payload.remove("col_3");
+        let result = run_onnx_model(onnx_path, &model_metadata, &payload);
+        assert_eq!(
+            result.unwrap_err(),
+            "No value provided for model input col_3"
+        );
+    }
+}
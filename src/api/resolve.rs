@@ -0,0 +1,356 @@
+use crate::api::list_cards::{get_cards, get_registry};
+use crate::api::types;
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Reference to one or more cards in the model registry
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum ModelSelector {
+    /// Card uid
+    Uid(String),
+    /// name@version
+    Version { name: String, version: String },
+    /// name@latest
+    Latest { name: String },
+    /// name@last:N
+    Last { name: String, count: usize },
+    /// tag:key=value
+    Tag { key: String, value: String },
+}
+
+impl FromStr for ModelSelector {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid model reference {}, expected uid, name@version, name@latest, name@last:N or tag:key=value",
+                value
+            )
+        };
+
+        if let Some(tag) = value.strip_prefix("tag:") {
+            let (key, tag_value) = tag.split_once('=').ok_or_else(invalid)?;
+            if key.is_empty() {
+                return Err(invalid());
+            }
+            return Ok(ModelSelector::Tag {
+                key: key.to_string(),
+                value: tag_value.to_string(),
+            });
+        }
+
+        let (name, spec) = match value.split_once('@') {
+            Some((name, spec)) => (name.to_string(), spec),
+            None if !value.is_empty() => return Ok(ModelSelector::Uid(value.to_string())),
+            None => return Err(invalid()),
+        };
+
+        if name.is_empty() || spec.is_empty() {
+            return Err(invalid());
+        }
+
+        if spec == "latest" {
+            return Ok(ModelSelector::Latest { name });
+        }
+
+        if let Some(count) = spec.strip_prefix("last:") {
+            let count: usize = count.parse().map_err(|_| invalid())?;
+            if count == 0 {
+                return Err(invalid());
+            }
+            return Ok(ModelSelector::Last { name, count });
+        }
+
+        Ok(ModelSelector::Version {
+            name,
+            version: spec.to_string(),
+        })
+    }
+}
+
+impl TryFrom<String> for ModelSelector {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for ModelSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ModelSelector::Uid(uid) => write!(f, "{}", uid),
+            ModelSelector::Version { name, version } => write!(f, "{}@{}", name, version),
+            ModelSelector::Latest { name } => write!(f, "{}@latest", name),
+            ModelSelector::Last { name, count } => write!(f, "{}@last:{}", name, count),
+            ModelSelector::Tag { key, value } => write!(f, "tag:{}={}", key, value),
+        }
+    }
+}
+
+/// Orders cards from newest to oldest version
+///
+/// Versions that are not valid semver are ordered after valid ones, newest date first.
+///
+/// * `a` - first card
+/// * `b` - second card
+fn compare_card_versions(a: &types::Card, b: &types::Card) -> Ordering {
+    match (
+        semver::Version::parse(&a.version),
+        semver::Version::parse(&b.version),
+    ) {
+        (Ok(a_version), Ok(b_version)) => b_version.cmp(&a_version),
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => b.date.cmp(&a.date),
+    }
+}
+
+/// Selects the cards matching a selector from a registry listing
+///
+/// Versions must match exactly, so a mistyped version never resolves to another model.
+///
+/// * `selector` - model selector
+/// * `cards` - cards listed for the selector
+fn select_cards(
+    selector: &ModelSelector,
+    mut cards: Vec<types::Card>,
+) -> Result<Vec<types::Card>, String> {
+    cards.sort_by(compare_card_versions);
+
+    let selected = match selector {
+        ModelSelector::Uid(uid) => cards.into_iter().filter(|c| &c.uid == uid).collect(),
+        ModelSelector::Version { name, version } => {
+            match cards.into_iter().find(|c| &c.version == version) {
+                Some(card) => vec![card],
+                None => return Err(format!("No card {} with version {}", name, version)),
+            }
+        }
+        ModelSelector::Latest { .. } => cards.into_iter().take(1).collect(),
+        ModelSelector::Last { count, .. } => cards.into_iter().take(*count).collect(),
+        ModelSelector::Tag { .. } => cards,
+    };
+
+    Ok(selected)
+}
+
+/// Builds the model registry list request for a selector
+///
+/// * `selector` - model selector
+fn build_list_request(selector: &ModelSelector) -> types::ListTableRequest {
+    let mut request = types::ListTableRequest {
+        table_name: get_registry("model"),
+        name: None,
+        team: None,
+        version: None,
+        uid: None,
+        limit: None,
+        tags: None,
+        max_date: None,
+    };
+
+    match selector {
+        ModelSelector::Uid(uid) => request.uid = Some(uid.clone()),
+        ModelSelector::Version { name, version } => {
+            request.name = Some(name.clone());
+            request.version = Some(version.clone());
+        }
+        ModelSelector::Latest { name } | ModelSelector::Last { name, .. } => {
+            request.name = Some(name.clone())
+        }
+        ModelSelector::Tag { key, value } => {
+            request.tags = Some(HashMap::from([(key.clone(), value.clone())]))
+        }
+    }
+
+    request
+}
+
+/// Resolves a model selector to model registry cards
+///
+/// # Arguments
+///
+/// * `selector` - Model selector
+pub async fn resolve_cards(selector: &ModelSelector) -> Result<Vec<types::Card>, String> {
+    let response = get_cards(&build_list_request(selector)).await?;
+    let cards = select_cards(selector, response.cards)?;
+
+    if cards.is_empty() {
+        return Err(format!("No model found for {}", selector));
+    }
+
+    Ok(cards)
+}
+
+/// Resolves the newest version of a model matching a semver requirement
+///
+/// # Arguments
+///
+/// * `name` - Model name
+/// * `requirement` - Version requirement (e.g. ^1.2)
+pub async fn resolve_version_req(
+    name: &str,
+    requirement: &semver::VersionReq,
+) -> Result<types::Card, String> {
+    let selector = ModelSelector::Latest {
+        name: name.to_string(),
+    };
+    let mut cards = get_cards(&build_list_request(&selector)).await?.cards;
+    cards.sort_by(compare_card_versions);
+
+    cards
+        .into_iter()
+        .find(|card| {
+            semver::Version::parse(&card.version)
+                .map(|version| requirement.matches(&version))
+                .unwrap_or(false)
+        })
+        .ok_or_else(|| format!("No version of {} matches {}", name, requirement))
+}
+
+/// Resolves a model selector to model uids
+///
+/// Uids are passed through without querying the registry.
+///
+/// # Arguments
+///
+/// * `selector` - Model selector
+pub async fn resolve_uids(selector: &ModelSelector) -> Result<Vec<String>, String> {
+    match selector {
+        ModelSelector::Uid(uid) => Ok(vec![uid.clone()]),
+        _ => Ok(resolve_cards(selector)
+            .await?
+            .into_iter()
+            .map(|card| card.uid)
+            .collect()),
+    }
+}
+
+/// Resolves a model selector that must refer to exactly one model
+///
+/// # Arguments
+///
+/// * `selector` - Model selector
+pub async fn resolve_uid(selector: &ModelSelector) -> Result<String, String> {
+    let mut uids = resolve_uids(selector).await?;
+
+    if uids.len() != 1 {
+        return Err(format!(
+            "Expected a single model for {}, found {}",
+            selector,
+            uids.len()
+        ));
+    }
+
+    Ok(uids.remove(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(version: &str, uid: &str) -> types::Card {
+        types::Card {
+            name: "model".to_string(),
+            team: "team".to_string(),
+            date: "2023-08-01".to_string(),
+            user_email: "fake_email".to_string(),
+            version: version.to_string(),
+            uid: uid.to_string(),
+            tags: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_parse_model_selector() {
+        assert_eq!(
+            "model@1.0.0".parse::<ModelSelector>().unwrap(),
+            ModelSelector::Version {
+                name: "model".to_string(),
+                version: "1.0.0".to_string()
+            }
+        );
+        assert_eq!(
+            "model@latest".parse::<ModelSelector>().unwrap(),
+            ModelSelector::Latest {
+                name: "model".to_string()
+            }
+        );
+        assert_eq!(
+            "model@last:3".parse::<ModelSelector>().unwrap(),
+            ModelSelector::Last {
+                name: "model".to_string(),
+                count: 3
+            }
+        );
+        assert_eq!(
+            "tag:stage=prod".parse::<ModelSelector>().unwrap(),
+            ModelSelector::Tag {
+                key: "stage".to_string(),
+                value: "prod".to_string()
+            }
+        );
+        assert_eq!(
+            "abc123".parse::<ModelSelector>().unwrap(),
+            ModelSelector::Uid("abc123".to_string())
+        );
+
+        assert_eq!(
+            "model@last:3".parse::<ModelSelector>().unwrap().to_string(),
+            "model@last:3"
+        );
+
+        for invalid in [
+            "",
+            "@1.0.0",
+            "model@",
+            "model@last:0",
+            "model@last:x",
+            "tag:prod",
+        ] {
+            assert!(invalid.parse::<ModelSelector>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_select_cards() {
+        let cards = vec![
+            card("1.2.0", "c"),
+            card("1.10.0", "d"),
+            card("1.1.0", "b"),
+            card("1.0.0", "a"),
+        ];
+
+        let latest: ModelSelector = "model@latest".parse().unwrap();
+        let selected = select_cards(&latest, cards.clone()).unwrap();
+        assert_eq!(selected[0].uid, "d");
+
+        let last: ModelSelector = "model@last:3".parse().unwrap();
+        let uids: Vec<String> = select_cards(&last, cards.clone())
+            .unwrap()
+            .into_iter()
+            .map(|c| c.uid)
+            .collect();
+        assert_eq!(uids, vec!["d", "c", "b"]);
+
+        let version: ModelSelector = "model@1.1.0".parse().unwrap();
+        let selected = select_cards(&version, cards.clone()).unwrap();
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected[0].uid, "b");
+
+        // a version without an exact match is an error, not the newest card
+        let typo: ModelSelector = "model@1.1".parse().unwrap();
+        assert_eq!(
+            select_cards(&typo, cards.clone()).unwrap_err(),
+            "No card model with version 1.1"
+        );
+
+        let tag: ModelSelector = "tag:stage=prod".parse().unwrap();
+        assert_eq!(select_cards(&tag, cards).unwrap().len(), 4);
+    }
+}
@@ -0,0 +1,246 @@
+use crate::api::logging::{self, Level};
+use crate::api::utils;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Seconds a server version stays cached
+const VERSION_CACHE_TTL: u64 = 300;
+
+/// Time allowed for the version request
+const VERSION_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Oldest server version the cli supports
+const OLDEST_SUPPORTED_VERSION: &str = "1.0.0";
+
+/// First server version the cli has not been tested against
+const NEWEST_UNSUPPORTED_VERSION: &str = "2.0.0";
+
+/// Range of server versions the cli supports
+pub fn supported_versions() -> String {
+    format!(
+        ">={}, <{}",
+        OLDEST_SUPPORTED_VERSION, NEWEST_UNSUPPORTED_VERSION
+    )
+}
+
+/// Checks a server version against the supported range
+///
+/// Servers that do not report a version are assumed to be supported.
+/// Returns a warning for servers newer than the supported range.
+///
+/// # Arguments
+///
+/// * `version` - Server version
+pub fn check_version(version: Option<&semver::Version>) -> Result<Option<String>, String> {
+    let version = match version {
+        Some(version) => version,
+        None => return Ok(None),
+    };
+
+    if *version < semver::Version::parse(OLDEST_SUPPORTED_VERSION).unwrap() {
+        return Err(format!(
+            "opsml server {} is older than the supported versions ({}), upgrade the server",
+            version,
+            supported_versions()
+        ));
+    }
+
+    if *version >= semver::Version::parse(NEWEST_UNSUPPORTED_VERSION).unwrap() {
+        return Ok(Some(format!(
+            "opsml server {} is newer than the supported versions ({}), requests may fail",
+            version,
+            supported_versions()
+        )));
+    }
+
+    Ok(None)
+}
+
+/// Server version as cached on disk
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct CachedVersion {
+    uri: String,
+    version: Option<String>,
+    /// Seconds since the unix epoch
+    checked_at: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct VersionResponse {
+    version: String,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Cache file for a tracking uri
+///
+/// * `dir` - cache directory
+/// * `uri` - tracking uri
+fn cache_path(dir: &Path, uri: &str) -> PathBuf {
+    let hash = format!("{:x}", Sha256::digest(uri.as_bytes()));
+    dir.join(format!("server-{}.json", &hash[..16]))
+}
+
+/// Reads a cached server version that has not expired
+///
+/// * `path` - cache file
+/// * `uri` - tracking uri
+/// * `now` - seconds since the unix epoch
+fn read_cache(path: &Path, uri: &str, now: u64) -> Option<CachedVersion> {
+    let data = fs::read_to_string(path).ok()?;
+    let cached: CachedVersion = serde_json::from_str(&data).ok()?;
+
+    if cached.uri != uri || now.saturating_sub(cached.checked_at) >= VERSION_CACHE_TTL {
+        return None;
+    }
+
+    Some(cached)
+}
+
+/// Writes a server version to the cache, ignoring failures
+///
+/// * `path` - cache file
+/// * `cached` - server version
+fn write_cache(path: &Path, cached: &CachedVersion) {
+    let written = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(path, serde_json::to_string(cached).unwrap_or_default()));
+
+    if let Err(e) = written {
+        logging::log(
+            Level::Debug,
+            "failed to cache server version",
+            &[("error", e.to_string().into())],
+        );
+    }
+}
+
+/// Requests the server version
+///
+/// Returns None for servers without a version route, and an error when the
+/// server could not be reached.
+///
+/// * `uri` - tracking uri
+async fn fetch_server_version(uri: &str) -> Result<Option<String>, String> {
+    let client = reqwest::Client::builder()
+        .timeout(VERSION_REQUEST_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let response = client
+        .get(utils::OpsmlPaths::Version.url(uri))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !response.status().is_success() {
+        return Ok(None);
+    }
+
+    let body = response.text().await.map_err(|e| e.to_string())?;
+    Ok(serde_json::from_str::<VersionResponse>(&body)
+        .ok()
+        .map(|response| response.version))
+}
+
+/// Queries the server version and checks it is supported
+///
+/// The version is cached for a few minutes so consecutive commands do not repeat the request.
+/// Fails when the server is older than the supported versions.
+#[tokio::main]
+pub async fn check_server_version() -> Result<(), String> {
+    let uri = utils::tracking_uri()?;
+    let path = cache_path(&std::env::temp_dir().join("opsml-cli"), &uri);
+
+    let version = match read_cache(&path, &uri, now()) {
+        Some(cached) => cached.version,
+        None => match fetch_server_version(&uri).await {
+            Ok(version) => {
+                let cached = CachedVersion {
+                    uri: uri.clone(),
+                    version,
+                    checked_at: now(),
+                };
+                write_cache(&path, &cached);
+                cached.version
+            }
+            Err(e) => {
+                // the command itself reports the unreachable server
+                logging::log(
+                    Level::Debug,
+                    "failed to query server version",
+                    &[("error", e.into())],
+                );
+                None
+            }
+        },
+    };
+
+    let parsed = version
+        .as_deref()
+        .and_then(|v| semver::Version::parse(v).ok());
+    if parsed.is_none() {
+        logging::log(
+            Level::Info,
+            "server version unknown, assuming it is supported",
+            &[("version", version.clone().into())],
+        );
+    }
+
+    if let Some(warning) = check_version(parsed.as_ref())? {
+        logging::log(Level::Warn, &warning, &[]);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_version() {
+        let version = |v: &str| semver::Version::parse(v).unwrap();
+
+        assert_eq!(check_version(None).unwrap(), None);
+        assert_eq!(check_version(Some(&version("1.4.2"))).unwrap(), None);
+
+        let warning = check_version(Some(&version("2.1.0"))).unwrap();
+        assert!(warning
+            .unwrap()
+            .contains("newer than the supported versions"));
+
+        let error = check_version(Some(&version("0.9.0"))).unwrap_err();
+        assert!(error.contains("older than the supported versions (>=1.0.0, <2.0.0)"));
+    }
+
+    #[test]
+    fn test_version_cache() {
+        let dir = std::env::temp_dir().join("opsml_version_cache_test");
+        let uri = "http://localhost:8080";
+        let path = cache_path(&dir, uri);
+        assert_ne!(path, cache_path(&dir, "http://localhost:8081"));
+
+        let cached = CachedVersion {
+            uri: uri.to_string(),
+            version: Some("1.2.0".to_string()),
+            checked_at: 1000,
+        };
+        write_cache(&path, &cached);
+
+        assert_eq!(read_cache(&path, uri, 1000 + 10), Some(cached));
+        assert_eq!(read_cache(&path, uri, 1000 + VERSION_CACHE_TTL), None);
+        assert_eq!(read_cache(&path, "http://other", 1000), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -27,7 +27,7 @@ pub struct ModelDownloadRequest {
     pub read_path: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Card {
     pub name: String,
     pub team: String,
@@ -60,13 +60,13 @@ pub struct MetricTable {
 }
 
 #[derive(Tabled)]
-pub struct CompareMetricTable {
-    pub champion_name: String,
-    pub champion_version: Value,
+pub struct MetricSummaryTable {
     pub metric: String,
-    pub champion_value: Value,
-    pub challenger_value: Value,
-    pub challenger_win: bool,
+    pub count: usize,
+    pub min: String,
+    pub max: String,
+    pub last: String,
+    pub trend: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -86,15 +86,15 @@ pub struct CardTable {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ModelDataSchema {
-    data_type: String,
-    input_features: HashMap<String, Value>,
-    output_features: HashMap<String, Value>,
+    pub data_type: String,
+    pub input_features: HashMap<String, Value>,
+    pub output_features: HashMap<String, Value>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DataSchema {
-    model_data_schema: ModelDataSchema,
-    input_data_schema: Option<HashMap<String, Value>>,
+    pub model_data_schema: ModelDataSchema,
+    pub input_data_schema: Option<HashMap<String, Value>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
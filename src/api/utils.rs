@@ -1,18 +1,18 @@
+use crate::api::cassette;
+use crate::api::logging::{self, Level};
 use lazy_static::lazy_static;
-use reqwest::{self, Response};
+use reqwest::{self, header::CONTENT_TYPE, Response};
 use serde::Serialize;
+use serde_json::{Map, Value};
 use std::env;
+use std::fmt;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 
 lazy_static! {
-    static ref OPSML_TRACKING_URI: String = match env::var("OPSML_TRACKING_URI") {
-        Ok(val) =>
-            if val.ends_with("/") {
-                remove_suffix(&val, "/")
-            } else {
-                val
-            },
-        Err(_e) => panic!("No tracking uri set"),
-    };
+    static ref OPSML_TRACKING_URI: Option<String> = env::var("OPSML_TRACKING_URI")
+        .ok()
+        .map(|val| remove_suffix(&val, "/"));
 }
 
 pub enum OpsmlPaths {
@@ -21,28 +21,40 @@ pub enum OpsmlPaths {
     Download,
     Metric,
     CompareMetric,
+    HealthCheck,
+    Version,
 }
 
 impl OpsmlPaths {
-    pub fn as_str(&self) -> String {
-        match self {
-            OpsmlPaths::ListCard => format!("{}/opsml/cards/list", OPSML_TRACKING_URI.to_string()),
-            OpsmlPaths::MetadataDownload => {
-                format!("{}/opsml/models/metadata", OPSML_TRACKING_URI.to_string())
-            }
-            OpsmlPaths::Download => {
-                format!("{}/opsml/files/download", OPSML_TRACKING_URI.to_string())
-            }
-            OpsmlPaths::Metric => {
-                format!("{}/opsml/models/metrics", OPSML_TRACKING_URI.to_string())
-            }
-            OpsmlPaths::CompareMetric => {
-                format!(
-                    "{}/opsml/models/compare_metrics",
-                    OPSML_TRACKING_URI.to_string()
-                )
-            }
-        }
+    pub fn as_str(&self) -> Result<String, String> {
+        Ok(self.url(&tracking_uri()?))
+    }
+
+    /// Route url on a given server
+    ///
+    /// * `base` - server uri without a trailing slash
+    pub fn url(&self, base: &str) -> String {
+        let route = match self {
+            OpsmlPaths::ListCard => "opsml/cards/list",
+            OpsmlPaths::MetadataDownload => "opsml/models/metadata",
+            OpsmlPaths::Download => "opsml/files/download",
+            OpsmlPaths::Metric => "opsml/models/metrics",
+            OpsmlPaths::CompareMetric => "opsml/models/compare_metrics",
+            OpsmlPaths::HealthCheck => "opsml/healthcheck",
+            OpsmlPaths::Version => "opsml/version",
+        };
+        format!("{}/{}", base, route)
+    }
+}
+
+/// Tracking uri without a trailing slash
+///
+/// Replays need no server, recordings are matched on url paths.
+pub fn tracking_uri() -> Result<String, String> {
+    match OPSML_TRACKING_URI.as_ref() {
+        Some(uri) => Ok(uri.clone()),
+        None if cassette::replaying() => Ok(String::new()),
+        None => Err("OPSML_TRACKING_URI is not set".to_string()),
     }
 }
 
@@ -51,7 +63,7 @@ pub async fn check_args(
     version: &Option<String>,
     uid: &Option<String>,
 ) -> Result<(), String> {
-    let common_args = vec![name, version];
+    let common_args = [name, version];
     let has_common = common_args.iter().all(|i| i.is_none());
 
     let has_uid = uid.is_none();
@@ -69,31 +81,284 @@ pub async fn check_args(
 ///
 /// * `s` - A string slice
 /// * `suffix` - A string slice
-pub fn remove_suffix<'a>(s: &str, suffix: &str) -> String {
+pub fn remove_suffix(s: &str, suffix: &str) -> String {
     match s.strip_suffix(suffix) {
         Some(s) => s.to_string(),
         None => s.to_string(),
     }
 }
 
+/// Number of times a request is sent before a connection failure is fatal
+const MAX_REQUEST_ATTEMPTS: u32 = 3;
+
+/// Why a request made through `make_post_request`, or the command sending it, stopped
+#[derive(Debug, PartialEq)]
+pub enum RequestError {
+    /// The request was printed by --dry-run instead of being sent
+    DryRun,
+    /// The request, or handling its response, failed
+    Failed(String),
+}
+
+impl From<String> for RequestError {
+    fn from(e: String) -> Self {
+        RequestError::Failed(e)
+    }
+}
+
+impl From<&str> for RequestError {
+    fn from(e: &str) -> Self {
+        RequestError::Failed(e.to_string())
+    }
+}
+
+impl RequestError {
+    /// Adds context in front of a failure, leaving a dry run as it is
+    ///
+    /// * `context` - what was being done
+    pub fn context(self, context: &str) -> Self {
+        match self {
+            RequestError::DryRun => RequestError::DryRun,
+            RequestError::Failed(e) => RequestError::Failed(format!("{}: {}", context, e)),
+        }
+    }
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RequestError::DryRun => write!(f, "dry run, request not sent"),
+            RequestError::Failed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// How requests made through `make_post_request` are handled
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RequestMode {
+    /// Print requests instead of sending them, lookups are still sent
+    pub dry_run: bool,
+    /// Print each request as a curl command
+    pub print_curl: bool,
+}
+
+static REQUEST_MODE: OnceLock<RequestMode> = OnceLock::new();
+
+/// Sets how requests are handled for the rest of the process
+///
+/// * `mode` - request mode
+pub fn set_request_mode(mode: RequestMode) {
+    let _ = REQUEST_MODE.set(mode);
+}
+
+/// Whether requests are printed instead of sent
+pub fn dry_run() -> bool {
+    REQUEST_MODE.get().is_some_and(|mode| mode.dry_run)
+}
+
+/// Quotes a value for a posix shell
+///
+/// * `value` - value to quote
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Builds a curl command sending a json post request
+///
+/// * `url` - request url
+/// * `body` - json body
+pub fn curl_command(url: &str, body: &str) -> String {
+    format!(
+        "curl -X POST {} -H {} --data-raw {}",
+        shell_quote(url),
+        shell_quote("Content-Type: application/json"),
+        shell_quote(body)
+    )
+}
+
+/// Prints a request that is not sent
+///
+/// * `url` - request url
+/// * `payload` - request body
+/// * `mode` - request mode
+fn print_dry_run<T: Serialize>(url: &str, payload: &T, mode: RequestMode) {
+    if mode.print_curl {
+        println!(
+            "{}",
+            curl_command(url, &serde_json::to_string(payload).unwrap())
+        );
+    } else {
+        println!(
+            "POST {}\n{}",
+            url,
+            serde_json::to_string_pretty(payload).unwrap()
+        );
+    }
+}
+
 /// async post request for metadata
-pub async fn make_post_request<T: Serialize>(url: &str, payload: &T) -> Response {
-    let parsed_url = reqwest::Url::parse(url).unwrap();
+///
+/// With --dry-run the request is printed instead of sent and [`RequestError::DryRun`] is returned.
+pub async fn make_post_request<T: Serialize>(
+    url: &str,
+    payload: &T,
+) -> Result<Response, RequestError> {
+    let mode = REQUEST_MODE.get().copied().unwrap_or_default();
+
+    if mode.dry_run {
+        print_dry_run(url, payload, mode);
+        return Err(RequestError::DryRun);
+    }
+
+    Ok(send_post_request(url, payload, mode).await?)
+}
+
+/// Post request whose response is needed to build a later request
+///
+/// Lookups only read from the server, so they are sent even with --dry-run.
+pub async fn make_lookup_request<T: Serialize>(url: &str, payload: &T) -> Result<Response, String> {
+    let mode = REQUEST_MODE.get().copied().unwrap_or_default();
+    send_post_request(url, payload, mode).await
+}
+
+/// Sends a post request
+///
+/// Connection failures are retried, and each call is logged with its status,
+/// latency and size. Returns an error once the last attempt fails.
+async fn send_post_request<T: Serialize>(
+    url: &str,
+    payload: &T,
+    mode: RequestMode,
+) -> Result<Response, String> {
     let client = reqwest::Client::new();
+    let body = serde_json::to_vec(payload).map_err(|e| e.to_string())?;
 
-    return client.post(parsed_url).json(payload).send().await.unwrap();
+    if mode.print_curl {
+        // stdout is reserved for command output
+        eprintln!("{}", curl_command(url, &String::from_utf8_lossy(&body)));
+    }
+
+    if let Some(response) = cassette::replay(url, &body)? {
+        return Ok(response);
+    }
+
+    let parsed_url = reqwest::Url::parse(url).map_err(|e| format!("Invalid url {}: {}", url, e))?;
+
+    let start = Instant::now();
+    let mut attempt = 1;
+
+    loop {
+        let request = client
+            .post(parsed_url.clone())
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.clone())
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        if logging::enabled(Level::Debug) {
+            let headers: Map<String, Value> = request
+                .headers()
+                .iter()
+                .map(|(name, value)| {
+                    let value = value.to_str().unwrap_or_default();
+                    (
+                        name.to_string(),
+                        logging::redact_header(name.as_str(), value).into(),
+                    )
+                })
+                .collect();
+            logging::log(
+                Level::Debug,
+                "http request",
+                &[
+                    ("method", "POST".into()),
+                    ("url", url.into()),
+                    ("headers", Value::Object(headers)),
+                    ("request_bytes", body.len().into()),
+                    ("attempt", attempt.into()),
+                ],
+            );
+        }
+
+        match client.execute(request).await {
+            Ok(response) => {
+                logging::log(
+                    Level::Info,
+                    "http response",
+                    &[
+                        ("method", "POST".into()),
+                        ("url", url.into()),
+                        ("status", response.status().as_u16().into()),
+                        ("latency_ms", (start.elapsed().as_millis() as u64).into()),
+                        ("request_bytes", body.len().into()),
+                        ("response_bytes", response.content_length().into()),
+                        ("retries", (attempt - 1).into()),
+                    ],
+                );
+                return cassette::record(url, &body, response).await;
+            }
+            Err(e) if attempt < MAX_REQUEST_ATTEMPTS && (e.is_connect() || e.is_timeout()) => {
+                logging::log(
+                    Level::Warn,
+                    "http request failed, retrying",
+                    &[
+                        ("method", "POST".into()),
+                        ("url", url.into()),
+                        ("attempt", attempt.into()),
+                        ("error", e.to_string().into()),
+                    ],
+                );
+                tokio::time::sleep(Duration::from_millis(200 * attempt as u64)).await;
+                attempt += 1;
+            }
+            Err(e) => {
+                logging::log(
+                    Level::Error,
+                    "http request failed",
+                    &[
+                        ("method", "POST".into()),
+                        ("url", url.into()),
+                        ("latency_ms", (start.elapsed().as_millis() as u64).into()),
+                        ("retries", (attempt - 1).into()),
+                        ("error", e.to_string().into()),
+                    ],
+                );
+                return Err(format!("Request to {} failed: {}", url, e));
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_curl_command() {
+        assert_eq!(
+            curl_command("http://localhost/opsml/cards/list", r#"{"name":"it's"}"#),
+            r#"curl -X POST 'http://localhost/opsml/cards/list' -H 'Content-Type: application/json' --data-raw '{"name":"it'\''s"}'"#
+        );
+    }
+
+    #[test]
+    fn test_request_error_context() {
+        assert_eq!(
+            RequestError::from("404 Not Found").context("Failed to lock model"),
+            RequestError::Failed("Failed to lock model: 404 Not Found".to_string())
+        );
+        assert_eq!(
+            RequestError::DryRun.context("Failed to lock model"),
+            RequestError::DryRun
+        );
+    }
+
     #[test]
     fn test_remove_suffix() {
         let test_uri_with_slash = "http://localhost:8080/";
         let test_uri_without_slash = "http://localhost:8080";
-        let processed_with_slash_uri = remove_suffix(&test_uri_with_slash, "/");
-        let processed_without_slash_uri = remove_suffix(&test_uri_without_slash, "/");
+        let processed_with_slash_uri = remove_suffix(test_uri_with_slash, "/");
+        let processed_without_slash_uri = remove_suffix(test_uri_without_slash, "/");
         assert_eq!(processed_with_slash_uri, "http://localhost:8080");
         assert_eq!(processed_without_slash_uri, test_uri_without_slash);
     }
@@ -0,0 +1,323 @@
+use crate::api::download_file::{download_model_file, get_model_metadata};
+use crate::api::lockfile::{artifact_uri, ArtifactKind};
+use crate::api::logging::{self, Level};
+use crate::api::resolve::resolve_version_req;
+use crate::api::utils;
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+use std::{fs, io};
+
+/// Name of the symlink pointing at the active version
+pub const CURRENT_LINK: &str = "current";
+
+/// Suffix of version directories that are still downloading
+const PARTIAL_SUFFIX: &str = ".partial";
+
+/// Settings for watching a model
+pub struct WatchOptions {
+    /// Semver requirement versions must match
+    pub requirement: semver::VersionReq,
+    /// Time between registry checks
+    pub interval: Duration,
+    /// Artifacts to download for each version
+    pub artifacts: Vec<ArtifactKind>,
+    /// Shell command run after each update
+    pub hook: Option<String>,
+    /// Number of previous versions kept for rollback
+    pub keep: usize,
+    /// Check the registry once instead of polling
+    pub once: bool,
+}
+
+/// Orders version names from newest to oldest, invalid semver last
+///
+/// * `a` - first version
+/// * `b` - second version
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    match (semver::Version::parse(a), semver::Version::parse(b)) {
+        (Ok(a), Ok(b)) => b.cmp(&a),
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => b.cmp(a),
+    }
+}
+
+/// Selects the version directories to delete, keeping the newest `keep` besides the current one
+///
+/// * `versions` - downloaded versions
+/// * `current` - active version
+/// * `keep` - number of previous versions to keep
+fn versions_to_prune(mut versions: Vec<String>, current: &str, keep: usize) -> Vec<String> {
+    versions.retain(|version| version != current);
+    versions.sort_by(|a, b| compare_versions(a, b));
+    versions.into_iter().skip(keep).collect()
+}
+
+/// Returns the version the `current` symlink points at
+///
+/// * `model_dir` - model directory
+fn current_version(model_dir: &Path) -> Option<String> {
+    fs::read_link(model_dir.join(CURRENT_LINK))
+        .ok()
+        .and_then(|target| {
+            target
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+        })
+}
+
+/// Points the `current` symlink at a version directory
+///
+/// The new link is created next to the old one and renamed over it, so readers
+/// always see either the old or the new version.
+///
+/// * `model_dir` - model directory
+/// * `version` - version directory name
+#[cfg(unix)]
+fn swap_current(model_dir: &Path, version: &str) -> io::Result<()> {
+    let staging = model_dir.join(format!("{}.tmp", CURRENT_LINK));
+
+    match fs::remove_file(&staging) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+
+    // relative targets keep the link valid when the directory is mounted elsewhere
+    std::os::unix::fs::symlink(version, &staging)?;
+    fs::rename(&staging, model_dir.join(CURRENT_LINK))
+}
+
+#[cfg(not(unix))]
+fn swap_current(_model_dir: &Path, _version: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "watch-model requires symlink support",
+    ))
+}
+
+/// Lists the downloaded version directories of a model
+///
+/// * `model_dir` - model directory
+fn downloaded_versions(model_dir: &Path) -> Result<Vec<String>, String> {
+    let entries = fs::read_dir(model_dir)
+        .map_err(|e| format!("Failed to read {}: {}", model_dir.display(), e))?;
+
+    Ok(entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().map(|t| t.is_dir()).unwrap_or(false))
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| !name.ends_with(PARTIAL_SUFFIX))
+        .collect())
+}
+
+/// Downloads a model version into its own directory
+///
+/// Files are written to a staging directory that is renamed once complete.
+///
+/// * `uid` - card uid
+/// * `version_dir` - directory for the version
+/// * `artifacts` - artifacts to download
+async fn download_version(
+    uid: &str,
+    version_dir: &Path,
+    artifacts: &[ArtifactKind],
+) -> Result<(), utils::RequestError> {
+    let staging = PathBuf::from(format!("{}{}", version_dir.display(), PARTIAL_SUFFIX));
+    if staging.exists() {
+        fs::remove_dir_all(&staging)
+            .map_err(|e| format!("Failed to remove {}: {}", staging.display(), e))?;
+    }
+
+    let staging_dir = staging.to_string_lossy().to_string();
+    let metadata = get_model_metadata(None, None, Some(uid.to_string()), &staging_dir).await?;
+
+    for kind in artifacts.iter() {
+        let (uri, file) = artifact_uri(&metadata, *kind)?;
+        download_model_file(
+            &utils::OpsmlPaths::Download.as_str()?,
+            &uri,
+            &staging.join(file).to_string_lossy(),
+        )
+        .await?;
+    }
+
+    fs::rename(&staging, version_dir)
+        .map_err(|e| format!("Failed to move {}: {}", staging.display(), e).into())
+}
+
+/// Runs the post-update hook with the new version in its environment
+///
+/// * `hook` - shell command
+/// * `version` - active version
+/// * `version_dir` - directory of the active version
+fn run_hook(hook: &str, version: &str, version_dir: &Path) -> Result<(), String> {
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(hook)
+        .env("OPSML_MODEL_VERSION", version)
+        .env("OPSML_MODEL_DIR", version_dir)
+        .status()
+        .map_err(|e| format!("Failed to run hook: {}", e))?;
+
+    if !status.success() {
+        return Err(format!("Hook exited with {}", status));
+    }
+
+    Ok(())
+}
+
+/// Checks the registry once and activates a newer matching version
+///
+/// Returns the version that was activated, if any.
+///
+/// * `name` - model name
+/// * `model_dir` - model directory
+/// * `options` - watch settings
+async fn refresh_model(
+    name: &str,
+    model_dir: &Path,
+    options: &WatchOptions,
+) -> Result<Option<String>, utils::RequestError> {
+    let card = resolve_version_req(name, &options.requirement).await?;
+    let current = current_version(model_dir);
+
+    if current.as_deref() == Some(card.version.as_str()) {
+        return Ok(None);
+    }
+
+    let version_dir = model_dir.join(&card.version);
+    if !version_dir.exists() {
+        eprintln!(
+            "Downloading {} {} to {}",
+            name,
+            card.version,
+            version_dir.display()
+        );
+        download_version(&card.uid, &version_dir, &options.artifacts).await?;
+    }
+
+    swap_current(model_dir, &card.version)
+        .map_err(|e| format!("Failed to activate {}: {}", card.version, e))?;
+    eprintln!("Activated {} {}", name, card.version);
+
+    if let Some(hook) = &options.hook {
+        // a failing hook does not roll back the new version
+        if let Err(e) = run_hook(hook, &card.version, &version_dir) {
+            logging::log(
+                Level::Error,
+                "post-update hook failed",
+                &[
+                    ("version", card.version.clone().into()),
+                    ("error", e.into()),
+                ],
+            );
+        }
+    }
+
+    for version in versions_to_prune(downloaded_versions(model_dir)?, &card.version, options.keep) {
+        let path = model_dir.join(&version);
+        fs::remove_dir_all(&path)
+            .map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+        eprintln!("Removed {} {}", name, version);
+    }
+
+    Ok(Some(card.version))
+}
+
+/// Polls the registry and keeps the newest matching model version active
+///
+/// Versions are downloaded to `<write_dir>/<name>/<version>` and `<write_dir>/<name>/current`
+/// links to the active one.
+///
+/// # Arguments
+///
+/// * `name` - Model name
+/// * `write_dir` - Directory to download versions to
+/// * `options` - Watch settings
+#[tokio::main]
+pub async fn watch_model(
+    name: &str,
+    write_dir: &str,
+    options: &WatchOptions,
+) -> Result<(), utils::RequestError> {
+    let model_dir = Path::new(write_dir).join(name);
+    // a dry run leaves the write directory untouched
+    if !utils::dry_run() {
+        fs::create_dir_all(&model_dir)
+            .map_err(|e| format!("Failed to create {}: {}", model_dir.display(), e))?;
+    }
+
+    eprintln!(
+        "Watching {} {} every {}s",
+        name,
+        options.requirement,
+        options.interval.as_secs()
+    );
+
+    loop {
+        let result = refresh_model(name, &model_dir, options).await;
+
+        // a dry run stops at the first printed request instead of printing it every interval
+        if options.once || matches!(result, Err(utils::RequestError::DryRun)) {
+            return result.map(|_| ());
+        }
+
+        // registry outages should not stop the sidecar serving the current version
+        if let Err(e) = result {
+            logging::log(
+                Level::Warn,
+                "model refresh failed",
+                &[("name", name.into()), ("error", e.to_string().into())],
+            );
+        }
+
+        tokio::time::sleep(options.interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versions_to_prune() {
+        let versions: Vec<String> = ["1.0.0", "1.10.0", "1.2.0", "1.9.0", "dev"]
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+
+        assert_eq!(
+            versions_to_prune(versions.clone(), "1.10.0", 2),
+            vec!["1.0.0", "dev"]
+        );
+        assert_eq!(
+            versions_to_prune(versions, "1.2.0", 0),
+            vec!["1.10.0", "1.9.0", "1.0.0", "dev"]
+        );
+    }
+
+    #[test]
+    fn test_swap_current() {
+        let model_dir = std::env::temp_dir().join("opsml_watch_model_test");
+        for version in ["1.0.0", "1.1.0"] {
+            fs::create_dir_all(model_dir.join(version)).unwrap();
+        }
+        assert_eq!(current_version(&model_dir), None);
+
+        swap_current(&model_dir, "1.0.0").unwrap();
+        assert_eq!(current_version(&model_dir).as_deref(), Some("1.0.0"));
+
+        swap_current(&model_dir, "1.1.0").unwrap();
+        assert_eq!(current_version(&model_dir).as_deref(), Some("1.1.0"));
+        assert!(model_dir.join(CURRENT_LINK).join(".").is_dir());
+
+        // the link is not listed as a version
+        let mut versions = downloaded_versions(&model_dir).unwrap();
+        versions.sort();
+        assert_eq!(versions, vec!["1.0.0", "1.1.0"]);
+
+        fs::remove_dir_all(&model_dir).unwrap();
+    }
+}
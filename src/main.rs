@@ -1,21 +1,68 @@
+use api::cassette;
 use api::command_structs::{
-    CompareMetricArgs, DownloadModelArgs, ListCards, ModelMetadataArgs, ModelMetricArgs,
+    CompareMetricArgs, CompletionsArgs, DiffMetricsArgs, DownloadModelArgs, InspectOnnxArgs,
+    LeaderboardArgs, ListCards, LockArgs, ManArgs, MockServerArgs, ModelMetadataArgs,
+    ModelMetricArgs, PlotMetricsArgs, PredictArgs, SyncArgs, WatchModelArgs,
 };
-use api::download_file::download_model;
-use api::download_file::download_model_metadata;
+use api::compare_spec::build_compare_spec;
+use api::completions::{completions, man, COMPLETE_VAR};
+use api::diff_metrics::diff_metrics;
+use api::doctor::doctor;
+use api::download_file::{download_model, download_model_metadata, DownloadOutput};
+use api::inspect_onnx::inspect_onnx;
+use api::leaderboard::leaderboard;
 use api::list_cards::list_cards;
-use api::metrics::{compare_model_metrics, get_model_metrics};
+use api::lockfile::{lock, sync};
+use api::logging::{self, LogFormat};
+use api::metrics::{
+    compare_model_metrics, get_model_metrics, CompareError, CompareOutput, MetricFilter,
+};
+use api::mock_server::mock_server;
+use api::plot_metrics::plot_metrics;
+use api::predict::predict;
+use api::server::check_server_version;
+use api::utils;
+use api::watch_model::{watch_model, WatchOptions};
 mod api;
-use clap::command;
-use clap::Parser;
 use clap::Subcommand;
+use clap::{CommandFactory, Parser};
+use clap_complete::CompleteEnv;
+
+/// Exit code when a challenger does not meet the comparison gate
+const CHALLENGER_NOT_QUALIFIED_EXIT_CODE: i32 = 3;
+
+/// Exit code when the server fails to compare metrics
+const COMPARE_SERVER_ERROR_EXIT_CODE: i32 = 4;
 
 #[derive(Parser)]
 #[command(about = "CLI tool for Interacting with an Opsml server")]
-
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
+
+    /// Log http calls to stderr, repeat for request details (-vv)
+    #[arg(short = 'v', long = "verbose", action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Log line format
+    #[arg(long = "log-format", value_enum, default_value_t = LogFormat::Text, global = true)]
+    log_format: LogFormat,
+
+    /// Print the request a command would send instead of sending it, models are still resolved
+    #[arg(long = "dry-run", global = true)]
+    dry_run: bool,
+
+    /// Print each request as a curl command
+    #[arg(long = "print-curl", global = true)]
+    print_curl: bool,
+
+    /// Record every request and response into a directory
+    #[arg(long = "record", global = true, conflicts_with = "replay")]
+    record: Option<String>,
+
+    /// Replay responses recorded with --record instead of calling the server
+    #[arg(long = "replay", global = true)]
+    replay: Option<String>,
 }
 
 #[derive(Subcommand)]
@@ -31,6 +78,7 @@ enum Commands {
     /// # Example
     ///
     /// opsml-cli download-model-metadata --name model_name --version 1.0.0
+    /// OPSML_MODEL_UID=uid OPSML_READY_FILE=/ready opsml-cli download-model-metadata
     DownloadModelMetadata(ModelMetadataArgs),
     /// Download a model and its metadata from the model registry
     ///
@@ -38,96 +86,335 @@ enum Commands {
     ///
     /// opsml-cli download-model --name model_name --version 1.0.0
     /// opsml-cli download-model --name model_name --version 1.0.0 --no-onnx
+    /// OPSML_MODEL_NAME=model_name OPSML_MODEL_VERSION=1.0.0 OPSML_JSON_LOG=true opsml-cli download-model
+    ///
+    /// Every argument can be set from an OPSML_* environment variable, see --help
     DownloadModel(DownloadModelArgs),
     /// Retrieve model metrics
     ///
     /// # Example
     ///
     /// opsml-cli get-model-metrics --name model_name --version 1.0.0
+    /// opsml-cli get-model-metrics --uid uid --metric loss --full
+    /// opsml-cli get-model-metrics --uid uid --metric 'val_*' --min-step 100 --latest-only --output json
     GetModelMetrics(ModelMetricArgs),
     /// Compare model metrics
     ///
     /// # Example
     ///
     /// opsml-cli compare-model-metrics
+    /// opsml-cli compare-model-metrics --challenger model@1.1.0 --champion model@last:3
+    /// opsml-cli compare-model-metrics --metric-name mae:min,auc:max --challenger-uid uid --champion-uid uid
+    /// opsml-cli compare-model-metrics --spec comparison.yaml
+    /// opsml-cli compare-model-metrics --fail-on-loss --tolerance latency=1%
+    /// opsml-cli compare-model-metrics --spec comparison.yaml --bootstrap 1000
+    /// opsml-cli compare-model-metrics --spec comparison.yaml --report markdown --out report.md
+    /// opsml-cli compare-model-metrics --spec comparison.yaml --junit metrics.xml
+    ///
+    /// Exits with code 3 when the challenger does not qualify
     CompareModelMetrics(CompareMetricArgs),
+    /// Run a downloaded onnx model against its sample data or a payload
+    ///
+    /// # Example
+    ///
+    /// opsml-cli predict --write-dir .models
+    /// opsml-cli predict --write-dir .models --payload payload.json
+    Predict(PredictArgs),
+    /// Inspect an onnx model and cross-check it against its metadata
+    ///
+    /// # Example
+    ///
+    /// opsml-cli inspect-onnx .models/model.onnx
+    InspectOnnx(InspectOnnxArgs),
+    /// Rank model versions by a metric
+    ///
+    /// # Example
+    ///
+    /// opsml-cli leaderboard --name model --metric auc:max --top 10
+    /// opsml-cli leaderboard --team team --metric mae:min --output csv
+    Leaderboard(LeaderboardArgs),
+    /// Plot a metric for one or more models to an svg or png file
+    ///
+    /// # Example
+    ///
+    /// opsml-cli plot-metrics --uid uid --metric loss --out chart.svg
+    /// opsml-cli plot-metrics --uid uid1 --uid uid2 --metric auc --out chart.png
+    PlotMetrics(PlotMetricsArgs),
+    /// Show every metric logged by either of two models
+    ///
+    /// # Example
+    ///
+    /// opsml-cli diff-metrics --a model@1.0.0 --b model@1.1.0
+    /// opsml-cli diff-metrics --a uid1 --b uid2 --output json
+    DiffMetrics(DiffMetricsArgs),
+    /// Resolve the models in opsml.toml and pin them in opsml.lock
+    ///
+    /// # Example
+    ///
+    /// opsml-cli lock
+    /// opsml-cli lock --manifest service/opsml.toml --lockfile service/opsml.lock
+    Lock(LockArgs),
+    /// Download the models pinned in opsml.lock
+    ///
+    /// # Example
+    ///
+    /// opsml-cli sync
+    /// opsml-cli sync --lockfile service/opsml.lock
+    Sync(SyncArgs),
+    /// Poll the registry and keep the newest matching model version downloaded
+    ///
+    /// # Example
+    ///
+    /// opsml-cli watch-model --name model_name --version ^1.2 --interval 60s
+    /// opsml-cli watch-model --name model_name --keep 3 --hook 'curl -X POST localhost:8080/reload'
+    WatchModel(WatchModelArgs),
+    /// Check connectivity to and compatibility with the opsml server
+    ///
+    /// # Example
+    ///
+    /// opsml-cli doctor
+    Doctor,
+    /// Serve the opsml api from a directory of fixtures for offline use
+    ///
+    /// # Example
+    ///
+    /// opsml-cli mock-server --fixtures src/api/test_utils/fixtures --port 8090
+    MockServer(MockServerArgs),
+    /// Print a script registering shell completions, including card names and versions
+    ///
+    /// # Example
+    ///
+    /// source <(opsml-cli completions bash)
+    /// opsml-cli completions fish | source
+    Completions(CompletionsArgs),
+    /// Render man pages
+    ///
+    /// # Example
+    ///
+    /// opsml-cli man | man -l -
+    /// opsml-cli man --out-dir /usr/local/share/man/man1
+    Man(ManArgs),
 }
 
 fn main() -> Result<(), String> {
+    // answers completion requests from the scripts printed by `completions`
+    CompleteEnv::with_factory(Cli::command)
+        .var(COMPLETE_VAR)
+        .complete();
+
     let cli = Cli::parse();
+    logging::init(cli.verbose, cli.log_format);
+    utils::set_request_mode(utils::RequestMode {
+        dry_run: cli.dry_run,
+        print_curl: cli.print_curl,
+    });
+    cassette::init(cli.record.as_deref(), cli.replay.as_deref())?;
 
-    match &cli.command {
+    // local commands and doctor do not need a supported server version
+    let uses_server = !matches!(
+        cli.command,
+        None | Some(Commands::Predict(_))
+            | Some(Commands::InspectOnnx(_))
+            | Some(Commands::Doctor)
+            | Some(Commands::MockServer(_))
+            | Some(Commands::Completions(_))
+            | Some(Commands::Man(_))
+    );
+    if uses_server && !cli.dry_run && !cassette::replaying() {
+        check_server_version()?;
+    }
+
+    // a dry run stops at the first request that is printed instead of sent
+    match run_command(&cli.command) {
+        Ok(()) | Err(utils::RequestError::DryRun) => Ok(()),
+        Err(utils::RequestError::Failed(e)) => Err(e),
+    }
+}
+
+/// Runs a subcommand
+///
+/// * `command` - parsed subcommand
+fn run_command(command: &Option<Commands>) -> Result<(), utils::RequestError> {
+    match command {
         // subcommand for list cards
         Some(Commands::ListCards(args)) => {
-            let response = list_cards(
+            list_cards(
                 args.registry.as_str(),
                 args.name.as_deref(),
                 args.team.as_deref(),
                 args.version.as_deref(),
                 args.uid.as_deref(),
-                args.limit.clone(),
+                args.limit,
                 args.tag_name.clone(),
                 args.tag_value.clone(),
                 args.max_date.as_deref(),
-            );
-
-            match response {
-                Ok(response) => Ok(response),
-                Err(e) => Err(e.to_string()),
-            }
+            )?;
+            Ok(())
         }
 
         // subcommand for downloading model metadata
         Some(Commands::DownloadModelMetadata(args)) => {
+            let output = DownloadOutput {
+                ready_file: args.ready_file.clone(),
+                json_log: args.json_log,
+            };
             download_model_metadata(
                 args.name.clone(),
                 args.version.clone(),
                 args.uid.clone(),
                 &args.write_dir.clone(),
+                &output,
             )?;
             Ok(())
         }
         // subcommand for downloading a model
         Some(Commands::DownloadModel(args)) => {
+            let output = DownloadOutput {
+                ready_file: args.ready_file.clone(),
+                json_log: args.json_log,
+            };
             download_model(
                 args.name.clone(),
                 args.version.clone(),
                 args.uid.clone(),
                 &args.write_dir.clone(),
-                args.no_onnx.clone(),
-                args.onnx.clone(),
+                args.no_onnx,
+                args.onnx,
+                &output,
             )?;
             Ok(())
         }
         // subcommand for getting model metrics
         Some(Commands::GetModelMetrics(args)) => {
-            let response = get_model_metrics(
+            let filter = MetricFilter::new(
+                &args.metric,
+                &args.metric_regex,
+                args.min_step,
+                args.max_step,
+                args.latest_only,
+            )?;
+
+            get_model_metrics(
                 args.name.as_deref(),
                 args.version.as_deref(),
                 args.uid.as_deref(),
-            );
-
-            match response {
-                Ok(response) => Ok(response),
-                Err(e) => Err(e.to_string()),
-            }
+                &filter,
+                args.full,
+                args.output,
+            )
         }
 
         // subcommand for comparing model metrics
         Some(Commands::CompareModelMetrics(args)) => {
-            let response = compare_model_metrics(
-                &args.metric_name,
-                &args.lower_is_better,
-                &args.challenger_uid,
-                &args.champion_uid,
-            );
+            let spec = build_compare_spec(args)?;
+            let output = CompareOutput {
+                step_stats: args.step_stats || args.bootstrap.is_some(),
+                bootstrap: args.bootstrap,
+                report: args.report.zip(args.out.clone()),
+                junit: args.junit.clone(),
+            };
+            let response = compare_model_metrics(&spec, &output);
 
             match response {
-                Ok(response) => Ok(response),
-                Err(e) => Err(e.to_string()),
+                Ok(true) => Ok(()),
+                Ok(false) => std::process::exit(CHALLENGER_NOT_QUALIFIED_EXIT_CODE),
+                Err(CompareError::Server(e)) => {
+                    eprintln!("Error: {:?}", e);
+                    std::process::exit(COMPARE_SERVER_ERROR_EXIT_CODE)
+                }
+                Err(CompareError::Other(e)) => Err(e.into()),
+                Err(CompareError::DryRun) => Err(utils::RequestError::DryRun),
             }
         }
 
+        // subcommand for running a downloaded onnx model
+        Some(Commands::Predict(args)) => {
+            predict(&args.write_dir, args.payload.as_deref())?;
+            Ok(())
+        }
+
+        // subcommand for inspecting an onnx model
+        Some(Commands::InspectOnnx(args)) => {
+            inspect_onnx(&args.path)?;
+            Ok(())
+        }
+
+        // subcommand for ranking model versions by a metric
+        Some(Commands::Leaderboard(args)) => {
+            leaderboard(
+                args.name.as_deref(),
+                args.team.as_deref(),
+                &args.metric,
+                args.top,
+                args.output,
+            )?;
+            Ok(())
+        }
+
+        // subcommand for plotting model metrics
+        Some(Commands::PlotMetrics(args)) => {
+            plot_metrics(&args.uid, &args.metric, &args.out)?;
+            Ok(())
+        }
+
+        // subcommand for diffing metrics of two models
+        Some(Commands::DiffMetrics(args)) => {
+            diff_metrics(&args.a, &args.b, args.output)?;
+            Ok(())
+        }
+
+        // subcommand for pinning models from a manifest
+        Some(Commands::Lock(args)) => {
+            lock(&args.manifest, &args.lockfile)?;
+            Ok(())
+        }
+
+        // subcommand for downloading pinned models
+        Some(Commands::Sync(args)) => {
+            sync(&args.lockfile)?;
+            Ok(())
+        }
+
+        // subcommand for keeping a model version up to date
+        Some(Commands::WatchModel(args)) => {
+            let requirement = semver::VersionReq::parse(&args.version)
+                .map_err(|e| format!("Invalid version {}: {}", args.version, e))?;
+            let options = WatchOptions {
+                requirement,
+                interval: args.interval,
+                artifacts: args.artifact.clone(),
+                hook: args.hook.clone(),
+                keep: args.keep,
+                once: args.once,
+            };
+            watch_model(&args.name, &args.write_dir, &options)?;
+            Ok(())
+        }
+
+        // subcommand for diagnosing server connectivity
+        Some(Commands::Doctor) => {
+            doctor()?;
+            Ok(())
+        }
+
+        // subcommand for serving fixtures as an opsml server
+        Some(Commands::MockServer(args)) => {
+            mock_server(&args.fixtures, &args.host, args.port)?;
+            Ok(())
+        }
+
+        // subcommand for generating shell completions
+        Some(Commands::Completions(args)) => {
+            completions(Cli::command(), args.shell)?;
+            Ok(())
+        }
+
+        // subcommand for generating man pages
+        Some(Commands::Man(args)) => {
+            man(Cli::command(), args.out_dir.as_deref())?;
+            Ok(())
+        }
+
         None => Ok(()),
     }
 }